@@ -1,22 +1,36 @@
 use serde::{Serialize, Serializer};
 use std::collections::HashMap;
-use crate::transaction::{Transaction, AccountId, TransactionId, TransactionType};
+use crate::config::EngineConfig;
+use crate::credit;
+use crate::dispute::{DisputeAction, DisputeActivity};
+use crate::handler::Handlers;
+use crate::interest::Accrual;
+use crate::limit::Usage;
+use crate::transaction::{Transaction, AccountId, Money, Timestamp, TransactionId, TransactionType};
 use thiserror::Error;
 
 /// Amounts with serialized precision of four places past the decimal
-pub struct MoneyAggregate(pub(crate) f64);
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MoneyAggregate(pub f64);
+
+impl MoneyAggregate {
+    /// Value rounded to four places past the decimal
+    pub(crate) fn rounded(&self) -> f64 {
+        (self.0 * 1_0000.0).round() / 1_0000.0
+    }
+}
 
 impl Serialize for MoneyAggregate {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
-        serializer.serialize_f64((self.0 * 1_0000.0).round() / 1_0000.0)
+        serializer.serialize_f64(self.rounded())
     }
 }
 
 #[derive(Debug, Error)]
-pub(crate) enum Error {
+pub enum Error {
     #[error("account {0:?} is locked")]
     AccountLocked(AccountId),
 
@@ -37,8 +51,45 @@ pub(crate) enum Error {
 
     #[error("type of transaction {0:?} is invalid")]
     InvalidTransactionType(TransactionId),
+
+    #[error("account {0:?} is internal and does not accept transactions")]
+    InternalAccount(AccountId),
+
+    #[error("account {0:?} exceeds its {1} limit")]
+    LimitExceeded(AccountId, &'static str),
+
+    #[error("transaction {0:?} is rejected: {1}")]
+    Rejected(TransactionId, String),
+
+    #[error("adjustment {0:?} has no operator or reason code")]
+    AdjustmentUnattributed(TransactionId),
 }
 
+impl Error {
+    /// Variant name for grouping errors in reports
+    pub(crate) fn kind(&self) -> &'static str {
+        match self {
+            Error::AccountLocked(..) => "AccountLocked",
+            Error::TransactionNotFound(..) => "TransactionNotFound",
+            Error::InsufficientFunds(..) => "InsufficientFunds",
+            Error::AmountMissingWhenRequired(..) => "AmountMissingWhenRequired",
+            Error::AmountPresentWhenAmbiguous(..) => "AmountPresentWhenAmbiguous",
+            Error::InvalidTransactionState(..) => "InvalidTransactionState",
+            Error::InvalidTransactionType(..) => "InvalidTransactionType",
+            Error::InternalAccount(..) => "InternalAccount",
+            Error::LimitExceeded(..) => "LimitExceeded",
+            Error::Rejected(..) => "Rejected",
+            Error::AdjustmentUnattributed(..) => "AdjustmentUnattributed",
+        }
+    }
+}
+
+/// Names of the serialized account columns in order
+pub(crate) const COLUMNS: [&str; 5] = ["client", "available", "held", "total", "locked"];
+
+/// Names of the serialized account columns in order when credit lines are configured
+pub(crate) const CREDIT_COLUMNS: [&str; 7] = ["client", "available", "held", "total", "locked", "credit_used", "credit_remaining"];
+
 /// Client account keeps balances of client funds calculated as aggregates of transactions
 #[derive(Serialize)]
 pub struct Account {
@@ -51,12 +102,72 @@ pub struct Account {
     pub(crate) held: MoneyAggregate,
     /// Sum of funds available and held
     pub(crate) total: MoneyAggregate,
-    /// Account is locked for a chargeback, no transactions can be accepted
+    /// Account is locked for a chargeback, only transactions settling open disputes are accepted by default
     pub(crate) locked: bool,
+    /// Account exceeds the dispute policy without being locked
+    #[serde(skip)]
+    pub(crate) flagged: bool,
+    /// Why the account is locked or flagged
+    #[serde(skip)]
+    pub(crate) reason: Option<String>,
 
     /// Keep all transactions of this account in memory for quick lookups by id
     #[serde(skip)]
     transactions: HashMap<TransactionId, Transaction>,
+    /// Applied transactions in input order with balances after each, used for statements
+    #[serde(skip)]
+    history: Vec<HistoryEntry>,
+    /// Deposits and withdrawals counted against period limits
+    #[serde(skip)]
+    usage: Usage,
+    /// Interest accrued on available funds and not booked yet
+    #[serde(skip)]
+    accrual: Accrual,
+    /// Disputes and deposits the dispute policy is checked against
+    #[serde(skip)]
+    disputes: DisputeActivity,
+}
+
+/// Account with the use of its credit line
+#[derive(Serialize)]
+pub(crate) struct CreditAccount<'a> {
+    client: AccountId,
+    available: &'a MoneyAggregate,
+    held: &'a MoneyAggregate,
+    total: &'a MoneyAggregate,
+    locked: bool,
+    credit_used: MoneyAggregate,
+    credit_remaining: MoneyAggregate,
+}
+
+/// Balances of an account at a point in its history
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct Balance {
+    pub available: MoneyAggregate,
+    pub held: MoneyAggregate,
+    pub total: MoneyAggregate,
+}
+
+/// Transaction applied to an account with the balances right after it
+#[derive(Debug, Clone)]
+pub(crate) struct HistoryEntry {
+    pub(crate) id: TransactionId,
+    pub(crate) transaction_type: TransactionType,
+    /// Amount moved, for disputes, resolves and chargebacks it is the amount of the disputed transaction
+    pub(crate) amount: Money,
+    /// Fee charged to the client, for the internal fee account the amount is the fee booked
+    pub(crate) fee: Money,
+    pub(crate) timestamp: Option<Timestamp>,
+    pub(crate) balance: Balance,
+    /// Who made a manual adjustment and why
+    pub(crate) adjustment: Option<Adjustment>,
+}
+
+/// Operator and reason code of a manual adjustment
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct Adjustment {
+    pub(crate) operator: String,
+    pub(crate) reason: String,
 }
 
 impl Account {
@@ -65,13 +176,93 @@ impl Account {
         Self { 
             id,
             locked: false,
+            flagged: false,
+            reason: None,
             available: MoneyAggregate(0.0),
             held: MoneyAggregate(0.0),
             total: MoneyAggregate(0.0),
-            transactions: HashMap::new()
+            transactions: HashMap::new(),
+            history: Vec::new(),
+            usage: Usage::default(),
+            accrual: Accrual::default(),
+            disputes: DisputeActivity::default(),
          }
     }
 
+    /// Lock the account for a reason, returns whether it was unlocked before
+    pub(crate) fn lock(&mut self, reason: String) -> bool {
+        if self.locked {
+            return false;
+        }
+        self.locked = true;
+        self.reason = Some(reason);
+        true
+    }
+
+    pub fn id(&self) -> AccountId {
+        self.id
+    }
+
+    /// Account with credit used and remaining of limit
+    pub(crate) fn with_credit(&self, limit: Money) -> CreditAccount<'_> {
+        let (used, remaining) = credit::usage(self.available.0, limit);
+
+        CreditAccount {
+            client: self.id,
+            available: &self.available,
+            held: &self.held,
+            total: &self.total,
+            locked: self.locked,
+            credit_used: MoneyAggregate(used),
+            credit_remaining: MoneyAggregate(remaining),
+        }
+    }
+
+    /// Current balances
+    pub fn balance(&self) -> Balance {
+        Balance {
+            available: self.available,
+            held: self.held,
+            total: self.total,
+        }
+    }
+
+    /// Applied transactions in the order they were processed
+    pub(crate) fn history(&self) -> &[HistoryEntry] {
+        &self.history
+    }
+
+    /// Amount a fee of the transaction is calculated from, the disputed amount for disputes, resolves and chargebacks,
+    /// adjustments and custom types are free of fees
+    fn fee_base(&self, transaction: &Transaction) -> Option<Money> {
+        match transaction.transaction_type {
+            TransactionType::Deposit | TransactionType::Withdrawal => transaction.amount,
+            TransactionType::Dispute | TransactionType::Resolve | TransactionType::Chargeback => {
+                self.transactions.get(&transaction.id).and_then(|t| t.amount)
+            }
+            _ => None,
+        }
+    }
+
+    /// Available funds once the transaction is applied, a dispute holds the funds of a disputed deposit,
+    /// none for resolves and chargebacks, which settle regardless of their fee, and the types free of fees
+    fn available_after(&self, transaction: &Transaction) -> Option<Money> {
+        let available = self.available.0;
+        match (transaction.transaction_type, transaction.amount) {
+            (TransactionType::Deposit, Some(amount)) => Some(available + amount),
+            (TransactionType::Withdrawal, Some(amount)) => Some(available - amount),
+            (TransactionType::Dispute, _) => {
+                let disputed = self.transactions.get(&transaction.id)?;
+                let amount = disputed.amount?;
+                match disputed.transaction_type {
+                    TransactionType::Deposit => Some(available - amount),
+                    _ => Some(available),
+                }
+            }
+            _ => None,
+        }
+    }
+
     fn add_transaction(&mut self, transaction: Transaction) {
         self.transactions.insert(transaction.id, transaction);
     }
@@ -81,7 +272,7 @@ impl Account {
         Ok(transaction)
     }
 
-    fn deposit(&mut self, transaction: Transaction) -> Result<(), Error> {
+    fn deposit(&mut self, transaction: Transaction) -> Result<Money, Error> {
         match transaction.amount {
             Some(amount) => {
                 self.available.0 += amount;
@@ -89,18 +280,19 @@ impl Account {
                 
                 self.add_transaction(transaction);
                 
-                Ok(())
+                Ok(amount)
             }
             None => return Err(Error::AmountMissingWhenRequired(transaction.id))
         }
     }
 
-    fn withdraw(&mut self, transaction: Transaction) -> Result<(), Error> {
+    fn withdraw(&mut self, transaction: Transaction, config: &EngineConfig) -> Result<Money, Error> {
         match transaction.amount {
             Some(amount) => {
                 let available = self.available.0 - amount;
 
-                if available < 0.0 {
+                // available funds can go negative within a credit line
+                if available < -config.credit_limit(self.id) {
                     return Err(Error::InsufficientFunds(self.id))
                 }
 
@@ -109,13 +301,39 @@ impl Account {
                 
                 self.add_transaction(transaction);
 
-                Ok(())
+                Ok(amount)
             }
             None => return Err(Error::AmountMissingWhenRequired(transaction.id))
         }
     }
 
-    fn dispute(&mut self, transaction: Transaction) -> Result<(), Error> {
+    /// Manual credit or debit by an operator, a debit is checked like a withdrawal unless configured otherwise
+    fn adjust(&mut self, transaction: Transaction, config: &EngineConfig) -> Result<Money, Error> {
+        let amount = transaction.amount.ok_or(Error::AmountMissingWhenRequired(transaction.id))?;
+
+        if transaction.operator.is_none() || transaction.reason.is_none() {
+            return Err(Error::AdjustmentUnattributed(transaction.id));
+        }
+
+        let change = match transaction.transaction_type {
+            TransactionType::AdjustDebit => -amount,
+            _ => amount,
+        };
+
+        if change < 0.0 && !config.adjustments_bypass_checks && self.available.0 + change < -config.credit_limit(self.id) {
+            return Err(Error::InsufficientFunds(self.id));
+        }
+
+        self.available.0 += change;
+        self.total.0 += change;
+
+        // kept for lookups so a dispute of it is rejected as a type that is never disputable
+        self.add_transaction(transaction);
+
+        Ok(amount)
+    }
+
+    fn dispute(&mut self, transaction: Transaction, config: &EngineConfig) -> Result<Money, Error> {
         match transaction.amount {
             Some(..) => return Err(Error::AmountPresentWhenAmbiguous(transaction.id)),
             None => {
@@ -126,24 +344,30 @@ impl Account {
                     return Err(Error::InvalidTransactionState(transaction.id))
                 }
 
-                // if dispute can result in a chargeback then it only makes sense if disputed transaction is a deposit
-                if transaction.transaction_type != TransactionType::Deposit {
+                // if dispute can result in a chargeback then it only makes sense for the types configured as disputable
+                if !config.is_disputable(transaction.transaction_type) {
                     return Err(Error::InvalidTransactionType(transaction.id))
                 }
 
                 transaction.disputed = true;
 
+                let transaction_type = transaction.transaction_type;
                 let amount = transaction.amount.ok_or(Error::AmountMissingWhenRequired(transaction.id))?;
-                
-                self.available.0 -=  amount;
-                self.held.0 +=  amount;
 
-                Ok(())
+                match transaction_type {
+                    // hold deposited funds until the dispute is settled
+                    TransactionType::Deposit => self.available.0 -= amount,
+                    // withdrawn funds claimed back by the client are held until the dispute is settled
+                    _ => self.total.0 += amount,
+                }
+                self.held.0 += amount;
+
+                Ok(amount)
             }
         }
     }
 
-    fn resolve(&mut self, transaction: Transaction) -> Result<(), Error> {
+    fn resolve(&mut self, transaction: Transaction) -> Result<Money, Error> {
         match transaction.amount {
             Some(..) => return Err(Error::AmountPresentWhenAmbiguous(transaction.id)),
             None => {
@@ -155,17 +379,23 @@ impl Account {
 
                 transaction.disputed = false;
 
+                let transaction_type = transaction.transaction_type;
                 let amount = transaction.amount.ok_or(Error::AmountMissingWhenRequired(transaction.id))?;
-                
-                self.available.0 +=  amount;
-                self.held.0 -=  amount;
 
-                Ok(())
+                match transaction_type {
+                    // the deposit stands, release held funds back to the client
+                    TransactionType::Deposit => self.available.0 += amount,
+                    // the withdrawal stands, held funds leave the account
+                    _ => self.total.0 -= amount,
+                }
+                self.held.0 -= amount;
+
+                Ok(amount)
             }
         }
     }
 
-    fn chargeback(&mut self, transaction: Transaction) -> Result<(), Error> {
+    fn chargeback(&mut self, transaction: Transaction, config: &EngineConfig) -> Result<Money, Error> {
         match transaction.amount {
             Some(..) => return Err(Error::AmountPresentWhenAmbiguous(transaction.id)),
             None => {
@@ -175,31 +405,173 @@ impl Account {
                     return Err(Error::InvalidTransactionState(transaction.id));
                 }
 
+                let (id, transaction_type) = (transaction.id, transaction.transaction_type);
                 let amount = transaction.amount.ok_or(Error::AmountMissingWhenRequired(transaction.id))?;
-                
+
+                match transaction_type {
+                    // the deposit is reversed, held funds leave the account
+                    TransactionType::Deposit => self.total.0 -= amount,
+                    // the withdrawal is reversed, held funds return to the client
+                    _ => self.available.0 += amount,
+                }
                 self.held.0 -= amount;
-                self.total.0 -= amount;
 
-                self.locked = true;
+                if config.lock_on_chargeback {
+                    self.lock(format!("chargeback of transaction {id}"));
+                }
 
-                Ok(())
+                Ok(amount)
             }
         }
     }
 
-    /// Apply a transaction to this account's aggregates
-    pub(crate) fn apply_transaction(&mut self, transaction: Transaction) -> Result<(), Error> {
-        if self.locked {
+    /// Apply a custom type by its registered handler to the balances
+    fn custom(&mut self, transaction: Transaction, handlers: &Handlers) -> Result<Money, Error> {
+        let handler = handlers
+            .get(transaction.transaction_type)
+            .ok_or(Error::InvalidTransactionType(transaction.id))?;
+
+        let mut balance = self.balance();
+        let amount = handler.apply(self.id, &transaction, &mut balance)?;
+
+        self.available = balance.available;
+        self.held = balance.held;
+        self.total = balance.total;
+
+        Ok(amount)
+    }
+
+    /// Apply a transaction to this account's aggregates following the engine policies
+    pub(crate) fn apply_transaction(&mut self, mut transaction: Transaction, config: &EngineConfig, handlers: &Handlers) -> Result<(), Error> {
+        // interest accrues on the balance held until now whether the transaction is applied or not
+        if let Some(timestamp) = transaction.timestamp {
+            self.advance(timestamp, config);
+        }
+
+        if self.locked && !config.is_accepted_when_locked(transaction.transaction_type) {
             return Err(Error::AccountLocked(self.id));
         }
 
-        match transaction.transaction_type {
+        transaction.amount = transaction.amount.map(|amount| config.round(amount));
+
+        let (id, transaction_type, timestamp) = (transaction.id, transaction.transaction_type, transaction.timestamp);
+        let adjustment = match (transaction_type.is_adjustment(), &transaction.operator, &transaction.reason) {
+            (true, Some(operator), Some(reason)) => Some(Adjustment {
+                operator: operator.clone(),
+                reason: reason.clone(),
+            }),
+            _ => None,
+        };
+
+        // limits are consulted before deposits and withdrawals change any balance
+        // transactions without a timestamp or out of order count into the latest period,
+        // so without timestamps a per period limit caps the sum over all input
+        let period = timestamp.map_or(self.usage.period(), |t| config.limits.period(t).max(self.usage.period()));
+        if let Some(amount) = transaction.amount {
+            let used = self.usage.used(period, transaction_type);
+            config
+                .limits
+                .check(self.id, transaction_type, amount, used)
+                .map_err(|limit| Error::LimitExceeded(self.id, limit))?;
+        }
+
+        let fee = self.fee_base(&transaction).map_or(0.0, |amount| config.fee(self.id, transaction_type, amount));
+
+        // fees must be covered by the funds available after their transaction within the credit line,
+        // resolves and chargebacks are charged into a negative balance rather than leave the funds held
+        let available = self.available_after(&transaction).unwrap_or(Money::INFINITY);
+        if fee > 0.0 && available + config.credit_limit(self.id) < fee {
+            return Err(Error::InsufficientFunds(self.id));
+        }
+
+        let amount = match transaction_type {
             TransactionType::Deposit => self.deposit(transaction),
-            TransactionType::Withdrawal => self.withdraw(transaction),
-            TransactionType::Dispute => self.dispute(transaction),
+            TransactionType::Withdrawal => self.withdraw(transaction, config),
+            TransactionType::Dispute => self.dispute(transaction, config),
             TransactionType::Resolve => self.resolve(transaction),
-            TransactionType::Chargeback => self.chargeback(transaction)
+            TransactionType::Chargeback => self.chargeback(transaction, config),
+            TransactionType::Interest => Err(Error::InvalidTransactionType(id)),
+            TransactionType::AdjustCredit | TransactionType::AdjustDebit => self.adjust(transaction, config),
+            TransactionType::Custom(..) => self.custom(transaction, handlers),
+        }?;
+
+        // charged together with the transaction once it is applied
+        self.available.0 -= fee;
+        self.total.0 -= fee;
+
+        self.usage.add(period, transaction_type, amount);
+
+        // disputes lock or flag the account once its dispute activity exceeds the policy
+        self.disputes.add(transaction_type, amount);
+        if transaction_type == TransactionType::Dispute && !self.locked && !self.flagged {
+            if let Some(reason) = config.disputes.exceeded(&self.disputes) {
+                match config.disputes.action {
+                    DisputeAction::Lock => {
+                        self.lock(reason);
+                    }
+                    DisputeAction::Flag => {
+                        self.flagged = true;
+                        self.reason = Some(reason);
+                    }
+                }
+            }
+        }
+
+        self.history.push(HistoryEntry {
+            id,
+            transaction_type,
+            amount,
+            fee,
+            timestamp,
+            balance: self.balance(),
+            adjustment,
+        });
+
+        Ok(())
+    }
+
+    /// Accrue interest on the available funds held since the last timestamp of the account
+    fn advance(&mut self, timestamp: Timestamp, config: &EngineConfig) {
+        let id = self.id;
+        self.accrual.advance(timestamp, self.available.0, |balance, secs| config.interest(id, balance, secs));
+    }
+
+    /// Book interest accrued until timestamp as a system generated transaction with id,
+    /// returns its history entry unless there is no interest to book
+    pub(crate) fn accrue(&mut self, id: TransactionId, until: Timestamp, config: &EngineConfig) -> Option<&HistoryEntry> {
+        self.advance(until, config);
+
+        let interest = config.round(self.accrual.take());
+        if interest == 0.0 {
+            return None;
         }
+
+        self.available.0 += interest;
+        self.total.0 += interest;
+
+        self.history.push(HistoryEntry {
+            id,
+            transaction_type: TransactionType::Interest,
+            amount: interest,
+            fee: 0.0,
+            timestamp: Some(until),
+            balance: self.balance(),
+            adjustment: None,
+        });
+
+        self.history.last()
+    }
+
+    /// Credit the fee a client is charged by a transaction to this internal fee account
+    pub(crate) fn book_fee(&mut self, charged: &HistoryEntry) {
+        self.available.0 += charged.fee;
+        self.total.0 += charged.fee;
+
+        self.history.push(HistoryEntry {
+            fee: charged.fee,
+            balance: self.balance(),
+            ..charged.clone()
+        });
     }
 }
 
@@ -217,7 +589,13 @@ mod tests {
                 held: MoneyAggregate(0.1),
                 total: MoneyAggregate(1.10001), // should round to 1.1
                 locked: false,
+                flagged: false,
+                reason: None,
                 transactions: HashMap::new(),
+                history: Vec::new(),
+                usage: Usage::default(),
+                accrual: Accrual::default(),
+                disputes: DisputeActivity::default(),
             },
             Account {
                 id: 2,
@@ -225,7 +603,13 @@ mod tests {
                 held: MoneyAggregate(0.0001),
                 total: MoneyAggregate(2.0001),
                 locked: true,
+                flagged: false,
+                reason: None,
                 transactions: HashMap::new(),
+                history: Vec::new(),
+                usage: Usage::default(),
+                accrual: Accrual::default(),
+                disputes: DisputeActivity::default(),
             },
         ];
 
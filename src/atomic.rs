@@ -0,0 +1,113 @@
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// File written under a temporary name in the target directory and renamed over the target on commit,
+/// so readers see either the previous file or the complete new one.
+/// The temporary file is removed if the writer is dropped without commit.
+pub(crate) struct AtomicFile {
+    file: Option<File>,
+    temp: PathBuf,
+    target: PathBuf,
+}
+
+impl AtomicFile {
+    pub(crate) fn create<P: AsRef<Path>>(target: P) -> io::Result<Self> {
+        let target = target.as_ref().to_path_buf();
+
+        let name = target
+            .file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "output path has no file name"))?;
+
+        // same directory keeps the rename on one file system
+        let mut temp_name = std::ffi::OsString::from(".");
+        temp_name.push(name);
+        temp_name.push(format!(".tmp-{}", process::id()));
+        let temp = target.with_file_name(temp_name);
+
+        let file = File::create(&temp)?;
+
+        Ok(Self {
+            file: Some(file),
+            temp,
+            target,
+        })
+    }
+
+    /// Flush and fsync the temporary file, rename it over the target and fsync the directory
+    pub(crate) fn commit(mut self) -> io::Result<()> {
+        let result = self.persist();
+        if result.is_err() {
+            let _ = fs::remove_file(&self.temp);
+        }
+        result
+    }
+
+    fn persist(&mut self) -> io::Result<()> {
+        if let Some(mut file) = self.file.take() {
+            file.flush()?;
+            file.sync_all()?;
+        }
+
+        fs::rename(&self.temp, &self.target)?;
+
+        // persist the rename itself, directories cannot be opened for sync on every platform
+        #[cfg(unix)]
+        if let Some(dir) = self.target.parent() {
+            let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+            File::open(dir)?.sync_all()?;
+        }
+
+        Ok(())
+    }
+
+    fn file(&mut self) -> &mut File {
+        self.file.as_mut().expect("atomic file is not written after commit")
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file().flush()
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if self.file.take().is_some() {
+            let _ = fs::remove_file(&self.temp);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tempdir::TempDir;
+
+    #[test]
+    /// target is replaced only on commit and no temporary file is left behind
+    fn replace_on_commit() {
+        let dir = TempDir::new("atomic");
+        let target = dir.join("accounts.csv");
+        fs::write(&target, "old").unwrap();
+
+        let mut file = AtomicFile::create(&target).unwrap();
+        file.write_all(b"new").unwrap();
+        assert_eq!(fs::read_to_string(&target).unwrap(), "old");
+        drop(file);
+        assert_eq!(fs::read_to_string(&target).unwrap(), "old");
+
+        let mut file = AtomicFile::create(&target).unwrap();
+        file.write_all(b"new").unwrap();
+        file.commit().unwrap();
+        assert_eq!(fs::read_to_string(&target).unwrap(), "new");
+
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    }
+}
@@ -0,0 +1,104 @@
+use csv::WriterBuilder;
+use serde::Serialize;
+use std::io::Write;
+
+use crate::account::{Account, MoneyAggregate};
+use crate::transaction::{AccountId, Timestamp, TransactionId, TransactionType};
+
+/// Row of the adjustments audit
+#[derive(Serialize)]
+struct Row<'a> {
+    client: AccountId,
+    tx: TransactionId,
+    #[serde(rename = "type")]
+    transaction_type: TransactionType,
+    timestamp: Option<Timestamp>,
+    amount: MoneyAggregate,
+    operator: &'a str,
+    reason: &'a str,
+    available: MoneyAggregate,
+    held: MoneyAggregate,
+    total: MoneyAggregate,
+}
+
+/// Write manual adjustments of accounts in client order as csv, each with the balances right after it
+pub(crate) fn write_adjustments<'a, I, W>(accounts: I, wtr: W) -> csv::Result<()>
+where
+    I: IntoIterator<Item = &'a Account>,
+    W: Write,
+{
+    let mut accounts: Vec<_> = accounts.into_iter().collect();
+    accounts.sort_unstable_by_key(|account| account.id());
+
+    let mut writer = WriterBuilder::new().from_writer(wtr);
+    for account in accounts {
+        for entry in account.history() {
+            let Some(adjustment) = &entry.adjustment else { continue };
+
+            writer.serialize(Row {
+                client: account.id(),
+                tx: entry.id,
+                transaction_type: entry.transaction_type,
+                timestamp: entry.timestamp,
+                amount: MoneyAggregate(entry.amount),
+                operator: &adjustment.operator,
+                reason: &adjustment.reason,
+                available: entry.balance.available,
+                held: entry.balance.held,
+                total: entry.balance.total,
+            })?;
+        }
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EngineConfig;
+    use crate::engine::Engine;
+
+    const CSV: &str = "\
+type,          client, tx, amount, operator, reason
+deposit,       2,      1,  10.0,   ,
+adjust_debit,  2,      2,  15.0,   ops-7,    FEE_REFUND
+adjust_credit, 2,      3,  5.0,    ops-7,    FEE_REFUND
+adjust_credit, 1,      4,  1.0,    ,         TYPO
+adjust_debit,  1,      5,  3.0,    ops-2,    CORRECTION
+dispute,       2,      3,  ,       ,
+";
+
+    #[test]
+    /// adjustments need an operator and reason, debits are checked like withdrawals unless configured, never disputable
+    fn adjustments() {
+        let mut engine = Engine::new(EngineConfig::default());
+        engine.input(CSV.as_bytes()).unwrap();
+
+        assert_eq!(engine.account(2).unwrap().available.0, 15.0);
+        let stats = engine.stats();
+        assert_eq!(stats.transactions[&TransactionType::AdjustCredit].rejected.get("AdjustmentUnattributed"), Some(&1));
+        assert_eq!(stats.transactions[&TransactionType::AdjustDebit].rejected.get("InsufficientFunds"), Some(&2));
+        assert_eq!(stats.transactions[&TransactionType::Dispute].rejected.get("InvalidTransactionType"), Some(&1));
+
+        let mut engine = Engine::new(EngineConfig {
+            adjustments_bypass_checks: true,
+            ..EngineConfig::default()
+        });
+        engine.input(CSV.as_bytes()).unwrap();
+        assert_eq!(engine.account(1).unwrap().available.0, -3.0);
+
+        let mut out = vec![];
+        write_adjustments(engine.accounts(), &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\
+client,tx,type,timestamp,amount,operator,reason,available,held,total
+1,5,adjust_debit,,3.0,ops-2,CORRECTION,-3.0,0.0,-3.0
+2,2,adjust_debit,,15.0,ops-7,FEE_REFUND,-5.0,0.0,-5.0
+2,3,adjust_credit,,5.0,ops-7,FEE_REFUND,0.0,0.0,0.0
+"
+        );
+    }
+}
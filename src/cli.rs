@@ -0,0 +1,774 @@
+use std::io::{self, BufWriter, LineWriter, Write};
+use std::fs::File;
+use std::net::TcpListener;
+use std::path::Path;
+use std::process::ExitCode;
+use std::sync::Arc;
+use anyhow::anyhow;
+use clap::{Args as ClapArgs, Parser, Subcommand};
+use thiserror::Error;
+use tracing::{error, span, warn, Level};
+
+use crate::{audit, engine, input, logging, metrics, reconcile, webhook};
+use crate::atomic::AtomicFile;
+use crate::compression::{Compression, Encoder};
+use crate::config::EngineConfig;
+use crate::dialect::{Dialect, Dialects};
+use crate::engine::{Engine, Rejection};
+use crate::event::JsonLines;
+use crate::input::Input;
+use crate::logging::LogFormat;
+use crate::metrics::Metrics;
+use crate::rejects::RejectsReport;
+use crate::script::Scripts;
+use crate::review::{ReviewQueue, Status};
+use crate::statement::{Format, Period, Statement};
+use crate::transaction::{AccountId, Money, Timestamp, TransactionId};
+use crate::validate::Summary;
+use crate::webhook::{Webhook, WebhookSink, Webhooks};
+
+#[derive(Parser)]
+#[clap(author, version, about, args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Args {
+    /// Csv files with transactions processed in order, `-` for stdin, directories and globs are expanded,
+    /// accounts are written to stdout
+    #[clap(required = true)]
+    filenames: Vec<String>,
+
+    /// Print processing statistics to stderr
+    #[clap(long)]
+    stats: bool,
+
+    /// Write processing statistics into a json file
+    #[clap(long, value_name = "PATH")]
+    stats_json: Option<String>,
+
+    #[clap(flatten)]
+    output: OutputArgs,
+
+    /// Log filter: level like `debug` or directives like `payments_engine=info`
+    #[clap(long, global = true, env = logging::LOG_ENV, default_value = "warn")]
+    log_level: String,
+
+    /// Log line format
+    #[clap(long, global = true, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+
+    /// Abort on the first record that cannot be parsed or applied instead of ignoring it
+    #[clap(long, global = true)]
+    fail_fast: bool,
+
+    /// Toml file with the csv dialect of input files
+    #[clap(long, global = true, value_name = "PATH")]
+    dialect: Option<String>,
+
+    /// Csv dialect of input files matching a glob, checked in order before `--dialect`
+    #[clap(long, global = true, value_name = "PATTERN=PATH")]
+    file_dialect: Vec<String>,
+
+    /// Write rejected records with the stage and reason they are rejected at into a csv file
+    #[clap(long, global = true, value_name = "PATH")]
+    rejects: Option<String>,
+
+    /// Json file with transactions held by risk rules for review, read before and replaced after processing
+    #[clap(long, global = true, value_name = "PATH")]
+    review_queue: Option<String>,
+
+    /// Rhai script defining `validate(tx, account)` run on every transaction, may be repeated
+    #[clap(long, global = true, value_name = "PATH")]
+    script: Vec<String>,
+
+    /// Write an event of every change of an account as json lines into a file, `-` for stdout
+    /// when accounts are written with `--output`
+    #[clap(long, global = true, value_name = "PATH")]
+    events: Option<String>,
+
+    /// Toml file with engine policies, see `config print-defaults`
+    #[clap(long, global = true, value_name = "PATH")]
+    config: Option<String>,
+
+    /// Toml file with deposit and withdrawal limits laid out as the `limits` section, replaces that of the config
+    #[clap(long, global = true, value_name = "PATH")]
+    limits: Option<String>,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+/// Where and how accounts are written
+#[derive(ClapArgs)]
+struct OutputArgs {
+    /// Write accounts into a file replaced atomically instead of stdout
+    #[clap(id = "output", long = "output", value_name = "PATH")]
+    path: Option<String>,
+
+    /// Compress accounts output, by default implied by the output file extension `.gz`, `.zst` or `.bz2`,
+    /// compressed input is detected automatically
+    #[clap(id = "output_compression", long = "output-compression", value_enum)]
+    compression: Option<Compression>,
+
+    /// Toml file with the csv dialect of accounts output
+    #[clap(id = "output_dialect", long = "output-dialect", value_name = "PATH")]
+    dialect: Option<String>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Process transactions and print a statement of one client account for a period
+    Statement {
+        /// Csv files with transactions as in processing
+        #[clap(required = true)]
+        filenames: Vec<String>,
+        /// Client account id
+        #[clap(long)]
+        client: AccountId,
+        /// First transaction id of the period
+        #[clap(long)]
+        from_tx: Option<TransactionId>,
+        /// Last transaction id of the period
+        #[clap(long)]
+        to_tx: Option<TransactionId>,
+        /// Start of the period in seconds since the unix epoch
+        #[clap(long)]
+        from_time: Option<Timestamp>,
+        /// End of the period in seconds since the unix epoch
+        #[clap(long)]
+        to_time: Option<Timestamp>,
+        #[clap(long, value_enum, default_value_t = Format::Csv)]
+        format: Format,
+    },
+    /// Process transactions and compare accounts with expected balances,
+    /// print discrepancies to stdout and exit with 1 if there are any
+    Reconcile {
+        /// Csv files with transactions as in processing
+        #[clap(required = true)]
+        filenames: Vec<String>,
+        /// Csv file with expected accounts in the output schema `client,available,held,total,locked`
+        expected: String,
+        /// Maximum difference of amounts considered equal
+        #[clap(long, default_value_t = reconcile::DEFAULT_TOLERANCE)]
+        tolerance: Money,
+    },
+    /// Parse and apply transactions without producing balances,
+    /// print the number of rejected records grouped by reason
+    Validate {
+        /// Csv files with transactions as in processing
+        #[clap(required = true)]
+        filenames: Vec<String>,
+        /// Exit with 1 if the fraction of rejected records exceeds this limit, e.g. 0.05
+        #[clap(long)]
+        max_reject_rate: Option<f64>,
+    },
+    /// Long running mode: process transactions as they arrive and expose metrics over http,
+    /// accounts are written to stdout when the input ends
+    Serve {
+        /// Csv files with transactions as in processing, stdin by default
+        #[clap(default_value = input::STDIN)]
+        filenames: Vec<String>,
+        /// Address to serve Prometheus metrics on at `/metrics`
+        #[clap(long, default_value = "127.0.0.1:9898")]
+        metrics_addr: String,
+        /// Json lines file with webhook deliveries not acknowledged yet, required with webhooks in the config
+        #[clap(long, value_name = "PATH")]
+        webhook_outbox: Option<String>,
+        #[clap(flatten)]
+        output: OutputArgs,
+    },
+    /// Process transactions, book interest of every account accrued until a point in time
+    /// and write accounts as in processing
+    Accrue {
+        /// Csv files with transactions as in processing
+        #[clap(required = true)]
+        filenames: Vec<String>,
+        /// End of the accrual in seconds since the unix epoch
+        #[clap(long)]
+        until: Timestamp,
+        #[clap(flatten)]
+        output: OutputArgs,
+    },
+    /// Process transactions and print manual adjustments of all accounts with operator, reason code
+    /// and balances after each as csv
+    Audit {
+        /// Csv files with transactions as in processing
+        #[clap(required = true)]
+        filenames: Vec<String>,
+    },
+    /// Review transactions held by risk rules in the `--review-queue` file, approved transactions are applied
+    /// and declined ones discarded in their original place whenever their input is processed with the queue
+    Review {
+        #[clap(subcommand)]
+        command: ReviewCommand,
+    },
+    /// Engine policies configuration
+    Config {
+        #[clap(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum ReviewCommand {
+    /// Print held transactions with their review status as csv
+    List,
+    /// Approve pending transactions
+    Approve {
+        /// Transaction ids
+        #[clap(required = true)]
+        tx: Vec<TransactionId>,
+    },
+    /// Decline pending transactions
+    Decline {
+        /// Transaction ids
+        #[clap(required = true)]
+        tx: Vec<TransactionId>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Print the default engine policies as toml to start a config file from
+    PrintDefaults,
+}
+
+/// Errors of the command line, each maps to a documented exit code
+#[derive(Debug, Error)]
+enum CliError {
+    #[error("{0}")]
+    CheckFailed(String),
+
+    #[error("{0}: {1}")]
+    Config(String, anyhow::Error),
+
+    #[error("cannot open {0}: {1}")]
+    Open(String, io::Error),
+
+    #[error(transparent)]
+    Engine(#[from] engine::Error),
+
+    #[error("{0}: {1}")]
+    Input(String, engine::Error),
+
+    #[error("cannot parse expected accounts: {0}")]
+    Expected(csv::Error),
+
+    #[error("cannot write {0}: {1}")]
+    Write(&'static str, anyhow::Error),
+
+    #[error("account {0} not found")]
+    AccountNotFound(AccountId),
+}
+
+impl CliError {
+    /// Exit codes:
+    /// - 0 success
+    /// - 1 check failed: reconciliation discrepancies or reject rate above the limit
+    /// - 2 invalid command line arguments or configuration
+    /// - 3 input file or address cannot be opened
+    /// - 4 input cannot be read or parsed as a whole
+    /// - 5 record rejected with `--fail-fast`
+    /// - 6 output cannot be written
+    /// - 7 account not found
+    fn exit_code(&self) -> u8 {
+        let engine_exit_code = |e: &engine::Error| match e {
+            engine::Error::Input(..) => 4,
+            engine::Error::Rejected { .. } => 5,
+            engine::Error::Output(..) | engine::Error::Events(..) => 6,
+        };
+
+        match self {
+            CliError::CheckFailed(..) => 1,
+            CliError::Config(..) => 2,
+            CliError::Open(..) => 3,
+            CliError::Expected(..) => 4,
+            CliError::Engine(e) | CliError::Input(_, e) => engine_exit_code(e),
+            CliError::Write(..) => 6,
+            CliError::AccountNotFound(..) => 7,
+        }
+    }
+}
+
+/// Parse the command line, run the command and map its error to an exit code
+pub fn main() -> ExitCode {
+    let args = Args::parse();
+
+    logging::init(&args.log_level, args.log_format);
+
+    match run(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            error!("{e}");
+            ExitCode::from(e.exit_code())
+        }
+    }
+}
+
+fn run(args: Args) -> Result<(), CliError> {
+    let mut config = match &args.config {
+        Some(path) => EngineConfig::load(path).map_err(|e| CliError::Config(path.clone(), e.into()))?,
+        None => EngineConfig::default(),
+    };
+
+    if let Some(path) = &args.limits {
+        config.load_limits(path).map_err(|e| CliError::Config(path.clone(), e.into()))?;
+    }
+
+    // only the long running mode notifies webhooks
+    let webhooks = config.webhooks.clone();
+    let mut engine = Engine::new(config);
+    engine.set_fail_fast(args.fail_fast);
+
+    let dialects = load_dialects(args.dialect.as_deref(), &args.file_dialect)?;
+
+    if !args.script.is_empty() {
+        let mut scripts = Scripts::load(&args.script).map_err(|e| CliError::Config("script".to_string(), e.into()))?;
+        // the long running mode picks up edited scripts without a restart
+        scripts.set_hot_reload(matches!(args.command, Some(Command::Serve { .. })));
+        engine.set_scripts(scripts);
+    }
+
+    if let Some(path) = &args.review_queue {
+        engine.set_review_queue(ReviewQueue::load(path).map_err(|e| CliError::Config(path.clone(), e.into()))?);
+    }
+
+    // validation is a dry run, nothing it applies may leave the process
+    let dry_run = matches!(args.command, Some(Command::Validate { .. }));
+    if dry_run && (args.events.is_some() || args.rejects.is_some()) {
+        return Err(CliError::Config("validate".to_string(), anyhow!("--events and --rejects cannot be used with validate")));
+    }
+
+    if let Some(path) = &args.events {
+        // events and accounts or reports on the same stream would make neither parseable
+        if path == "-" && writes_stdout(&args) {
+            return Err(CliError::Config("events".to_string(), anyhow!("`--events -` needs the accounts written with --output")));
+        }
+
+        let writer: Box<dyn Write + Send> = match path.as_str() {
+            "-" => Box::new(io::stdout()),
+            // the long running mode hands every event on as it happens
+            _ if matches!(args.command, Some(Command::Serve { .. })) => {
+                Box::new(LineWriter::new(File::create(path).map_err(|e| CliError::Write("events", e.into()))?))
+            }
+            _ => Box::new(BufWriter::new(File::create(path).map_err(|e| CliError::Write("events", e.into()))?)),
+        };
+        engine.add_event_sink(Box::new(JsonLines::new(writer)));
+    }
+
+    let mut rejects = match &args.rejects {
+        Some(path) => Some(RejectsReport::new(File::create(path).map_err(|e| CliError::Write("rejects report", e.into()))?)),
+        None => None,
+    };
+
+    let review_queue = args.review_queue.clone().filter(|_| !dry_run);
+    let result = execute(args, &mut engine, &dialects, &mut rejects, webhooks);
+
+    // sinks and the review queue are finished on failure too to keep what was written and held so far,
+    // the error of the command comes first
+    result.and(finish(&mut engine, rejects, review_queue.as_deref()))
+}
+
+/// Command prints accounts or a report to stdout
+fn writes_stdout(args: &Args) -> bool {
+    match &args.command {
+        None => args.output.path.is_none(),
+        Some(Command::Serve { output, .. } | Command::Accrue { output, .. }) => output.path.is_none(),
+        Some(_) => true,
+    }
+}
+
+/// Run the command on an engine set up from the global arguments
+fn execute(
+    args: Args,
+    engine: &mut Engine,
+    dialects: &Dialects,
+    rejects: &mut Option<RejectsReport<File>>,
+    webhooks: Vec<Webhook>,
+) -> Result<(), CliError> {
+    match args.command {
+        None => {
+            input_all(engine, &args.filenames, dialects, rejects)?;
+            output(engine, &args.output)?;
+
+            if args.stats {
+                eprint!("{}", engine.stats());
+            }
+
+            if let Some(path) = args.stats_json {
+                let file = File::create(path).map_err(|e| CliError::Write("statistics", e.into()))?;
+                serde_json::to_writer_pretty(file, engine.stats()).map_err(|e| CliError::Write("statistics", e.into()))?;
+            }
+        }
+        Some(Command::Statement { filenames, client, from_tx, to_tx, from_time, to_time, format }) => {
+            input_all(engine, &filenames, dialects, rejects)?;
+
+            let account = engine.account(client).ok_or(CliError::AccountNotFound(client))?;
+
+            let period = Period { from_tx, to_tx, from_time, to_time };
+
+            Statement::new(account, &period)
+                .write(io::stdout(), format)
+                .map_err(|e| CliError::Write("statement", e))?;
+        }
+        Some(Command::Reconcile { filenames, expected, tolerance }) => {
+            let expected = open(&expected)?;
+
+            input_all(engine, &filenames, dialects, rejects)?;
+
+            let discrepancies = reconcile::reconcile(expected, engine.accounts(), tolerance).map_err(CliError::Expected)?;
+
+            reconcile::write_report(io::stdout(), &discrepancies).map_err(|e| CliError::Write("reconciliation report", e.into()))?;
+
+            if !discrepancies.is_empty() {
+                return Err(CliError::CheckFailed(format!("{} discrepancies found", discrepancies.len())));
+            }
+        }
+        Some(Command::Validate { filenames, max_reject_rate }) => {
+            let mut summary = Summary::default();
+            summary.records = input_all_with(engine, &filenames, dialects, rejects, |rejection| summary.reject(rejection))?;
+            print!("{summary}");
+
+            if let Some(limit) = max_reject_rate {
+                if summary.reject_rate() > limit {
+                    return Err(CliError::CheckFailed(format!("reject rate {:.4} exceeds limit {limit}", summary.reject_rate())));
+                }
+            }
+        }
+        Some(Command::Serve { filenames, metrics_addr, webhook_outbox, output }) => {
+            let listener = TcpListener::bind(&metrics_addr).map_err(|e| CliError::Open(metrics_addr, e))?;
+            let metrics = Arc::new(Metrics::default());
+            metrics::serve(listener, metrics.clone());
+
+            engine.set_metrics(metrics);
+
+            let webhooks = match (webhooks.is_empty(), webhook_outbox) {
+                (true, _) => None,
+                (false, None) => return Err(CliError::Config("webhooks".to_string(), anyhow!("--webhook-outbox is required"))),
+                (false, Some(path)) => {
+                    let webhooks = Arc::new(Webhooks::open(webhooks, &path).map_err(|e| CliError::Config(path, e.into()))?);
+                    webhook::spawn(webhooks.clone());
+                    engine.add_event_sink(Box::new(WebhookSink(webhooks.clone())));
+                    Some(webhooks)
+                }
+            };
+
+            let result = input_all(engine, &filenames, dialects, rejects).and_then(|()| self::output(engine, &output));
+
+            // events of the input read so far are delivered even if it fails
+            let pending = match &webhooks {
+                Some(webhooks) => webhooks.finish().map_err(|e| CliError::Write("webhook outbox", e.into())),
+                None => Ok(0),
+            };
+            if let Ok(pending @ 1..) = pending {
+                warn!(pending, "webhook deliveries left in the outbox for the next run");
+            }
+            if let Some(dead_letters @ 1..) = webhooks.map(|webhooks| webhooks.dead_letters()) {
+                warn!(dead_letters, "webhook deliveries given up on are kept as dead letters in the outbox");
+            }
+            result.and(pending)?;
+        }
+        Some(Command::Accrue { filenames, until, output }) => {
+            input_all(engine, &filenames, dialects, rejects)?;
+            engine.accrue(until);
+            self::output(engine, &output)?;
+        }
+        Some(Command::Audit { filenames }) => {
+            input_all(engine, &filenames, dialects, rejects)?;
+
+            audit::write_adjustments(engine.accounts(), io::stdout()).map_err(|e| CliError::Write("audit", e.into()))?;
+        }
+        Some(Command::Review { command }) => {
+            if args.review_queue.is_none() {
+                return Err(CliError::Config("review".to_string(), anyhow!("--review-queue is required")));
+            }
+
+            let queue = engine.review_queue_mut();
+            let (tx, status) = match command {
+                ReviewCommand::List => {
+                    queue.write_list(io::stdout()).map_err(|e| CliError::Write("review queue", e.into()))?;
+                    (vec![], Status::Pending)
+                }
+                ReviewCommand::Approve { tx } => (tx, Status::Approved),
+                ReviewCommand::Decline { tx } => (tx, Status::Declined),
+            };
+            for tx in tx {
+                queue.decide(tx, status).map_err(|e| CliError::Config("review".to_string(), e.into()))?;
+            }
+        }
+        Some(Command::Config { command: ConfigCommand::PrintDefaults }) => {
+            print!("{}", EngineConfig::defaults_toml());
+        }
+    }
+
+    Ok(())
+}
+
+/// Flush event sinks, complete the rejects report and save the review queue, each one even if another fails
+fn finish(engine: &mut Engine, rejects: Option<RejectsReport<File>>, review_queue: Option<&str>) -> Result<(), CliError> {
+    let events = engine.flush_events().map_err(CliError::from);
+
+    let report = match rejects {
+        Some(report) => report.finish().map(|_| ()).map_err(|e| CliError::Write("rejects report", e.into())),
+        None => Ok(()),
+    };
+
+    let queue = match review_queue {
+        Some(path) => engine.review_queue_mut().save(path).map_err(|e| CliError::Write("review queue", e.into())),
+        None => Ok(()),
+    };
+
+    events.and(report).and(queue)
+}
+
+fn load_dialect(path: &str) -> Result<Dialect, CliError> {
+    Dialect::load(path).map_err(|e| CliError::Config(path.to_string(), e.into()))
+}
+
+/// Default dialect and `PATTERN=PATH` per file dialects of inputs
+fn load_dialects(default: Option<&str>, per_file: &[String]) -> Result<Dialects, CliError> {
+    let mut dialects = Dialects::default();
+
+    if let Some(path) = default {
+        dialects.default = load_dialect(path)?;
+    }
+
+    for arg in per_file {
+        let (pattern, path) = arg
+            .split_once('=')
+            .ok_or_else(|| CliError::Config(arg.clone(), anyhow!("expected --file-dialect PATTERN=PATH")))?;
+        let pattern = glob::Pattern::new(pattern).map_err(|e| CliError::Config(arg.clone(), e.into()))?;
+
+        dialects.per_file.push((pattern, load_dialect(path)?));
+    }
+
+    Ok(dialects)
+}
+
+fn open(arg: &str) -> Result<Box<dyn io::Read>, CliError> {
+    let input = if arg == input::STDIN { Input::Stdin } else { Input::File(arg.into()) };
+    input.open().map_err(|e| CliError::Open(arg.to_string(), e))
+}
+
+/// Expand input arguments and feed them into the engine in order
+fn input_all(engine: &mut Engine, args: &[String], dialects: &Dialects, rejects: &mut Option<RejectsReport<File>>) -> Result<(), CliError> {
+    input_all_with(engine, args, dialects, rejects, |_| {})?;
+    Ok(())
+}
+
+/// Same as `input_all` and hands every rejection to `on_reject` too, returns the number of records read
+fn input_all_with<F>(
+    engine: &mut Engine,
+    args: &[String],
+    dialects: &Dialects,
+    rejects: &mut Option<RejectsReport<File>>,
+    mut on_reject: F,
+) -> Result<usize, CliError>
+where
+    F: FnMut(&Rejection),
+{
+    let mut records = 0;
+
+    for arg in args {
+        for input in input::expand(&[arg]).map_err(|e| CliError::Open(arg.clone(), e))? {
+            let name = input.to_string();
+
+            let span = span!(Level::WARN, "input", input = %name);
+            let _enter = span.enter();
+
+            let rdr = input.open().map_err(|e| CliError::Open(name.clone(), e))?;
+            let dialect = dialects.for_input(&name);
+
+            // keep processing after a failed report write to surface the first one once the input is done
+            let mut write_error = None;
+            records += engine
+                .input_with(rdr, dialect, |record, rejection| {
+                    warn!(reason = rejection.kind(), "{rejection}");
+                    on_reject(&rejection);
+                    if let Some(report) = rejects.as_mut() {
+                        if let Err(e) = report.write(&name, record, &rejection) {
+                            write_error.get_or_insert(e);
+                        }
+                    }
+                })
+                .map_err(|e| CliError::Input(name.clone(), e))?;
+
+            if let Some(e) = write_error {
+                return Err(CliError::Write("rejects report", e.into()));
+            }
+        }
+    }
+
+    Ok(records)
+}
+
+/// Write accounts compressed to stdout or to a file replaced atomically once completely written
+fn output(engine: &Engine, args: &OutputArgs) -> Result<(), CliError> {
+    let write_error = |e: io::Error| CliError::Write("accounts", e.into());
+
+    let dialect = match &args.dialect {
+        Some(path) => load_dialect(path)?,
+        None => Dialect::default(),
+    };
+
+    match &args.path {
+        None => {
+            let mut out = Encoder::new(io::stdout(), args.compression.unwrap_or(Compression::None)).map_err(write_error)?;
+            engine.output_dialect(&mut out, &dialect)?;
+            out.finish().map_err(write_error)?;
+        }
+        Some(path) => {
+            let compression = args.compression.unwrap_or_else(|| Compression::from_extension(Path::new(path)));
+
+            let file = AtomicFile::create(path).map_err(write_error)?;
+            let mut out = Encoder::new(file, compression).map_err(write_error)?;
+            engine.output_dialect(&mut out, &dialect)?;
+            out.finish().and_then(AtomicFile::commit).map_err(write_error)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tempdir::TempDir;
+    use std::fs;
+
+    #[test]
+    /// events and rejects of the inputs read before a failure are kept
+    fn finish_on_failure() {
+        let dir = TempDir::new("cli");
+        let path = |name: &str| dir.arg(name);
+
+        fs::write(path("transactions.csv"), "type,client,tx,amount\ndeposit,1,1,abc\ndeposit,1,2,10.0\n").unwrap();
+
+        let args = Args::try_parse_from([
+            "payments-engine",
+            "--events",
+            &path("events.jsonl"),
+            "--rejects",
+            &path("rejects.csv"),
+            &path("transactions.csv"),
+            &path("missing.csv"),
+        ])
+        .unwrap();
+
+        let error = run(args).unwrap_err();
+        assert_eq!(error.exit_code(), 3);
+
+        let events = fs::read_to_string(path("events.jsonl")).unwrap();
+        assert_eq!(events.lines().count(), 1);
+        assert!(events.contains("\"event\":\"FundsDeposited\""));
+
+        let rejects = fs::read_to_string(path("rejects.csv")).unwrap();
+        assert_eq!(rejects.lines().count(), 2);
+        assert!(rejects.contains(",1,parse,"));
+    }
+
+    #[test]
+    /// events go to stdout only when the accounts do not
+    fn events_to_stdout() {
+        let parse = |args: &[&str]| Args::try_parse_from(["payments-engine"].iter().chain(args).chain(&["--events", "-"])).unwrap();
+
+        assert!(writes_stdout(&parse(&["in.csv"])));
+        assert!(!writes_stdout(&parse(&["in.csv", "--output", "accounts.csv"])));
+        assert!(writes_stdout(&parse(&["serve", "in.csv"])));
+        assert!(!writes_stdout(&parse(&["accrue", "in.csv", "--until", "0", "--output", "accounts.csv"])));
+        assert!(writes_stdout(&parse(&["audit", "in.csv"])));
+
+        let error = run(parse(&["in.csv"])).unwrap_err();
+        assert_eq!(error.exit_code(), 2);
+    }
+
+    #[test]
+    /// transactions held before a record rejected with fail fast stay in the saved review queue
+    fn review_queue_on_failure() {
+        let dir = TempDir::new("cli-review");
+        let path = |name: &str| dir.arg(name);
+
+        fs::write(path("config.toml"), "[[risk]]\nname = \"large\"\nrule = \"amount\"\nabove = 100.0\naction = \"hold\"\n").unwrap();
+        fs::write(path("transactions.csv"), "type,client,tx,amount\ndeposit,1,1,500.0\nwithdrawal,1,2,50.0\n").unwrap();
+
+        let args = Args::try_parse_from([
+            "payments-engine",
+            "--fail-fast",
+            "--config",
+            &path("config.toml"),
+            "--review-queue",
+            &path("queue.json"),
+            &path("transactions.csv"),
+        ])
+        .unwrap();
+
+        let error = run(args).unwrap_err();
+        assert_eq!(error.exit_code(), 5);
+
+        let queue = ReviewQueue::load(path("queue.json")).unwrap();
+        assert_eq!(queue.held().len(), 1);
+        assert_eq!(queue.held()[0].status, Status::Pending);
+    }
+
+    #[test]
+    /// validation holds transactions without saving the review queue and refuses to write events
+    fn validate_dry_run() {
+        let dir = TempDir::new("cli-validate");
+        let path = |name: &str| dir.arg(name);
+
+        fs::write(path("config.toml"), "[[risk]]\nname = \"large\"\nrule = \"amount\"\nabove = 100.0\naction = \"hold\"\n").unwrap();
+        fs::write(path("transactions.csv"), "type,client,tx,amount\ndeposit,1,1,500.0\n").unwrap();
+
+        let validate = |extra: &[&str]| {
+            let mut args = vec!["payments-engine", "validate"];
+            let (config, queue, transactions) = (path("config.toml"), path("queue.json"), path("transactions.csv"));
+            args.extend([transactions.as_str(), "--config", &config, "--review-queue", &queue]);
+            args.extend(extra);
+            run(Args::try_parse_from(args).unwrap())
+        };
+
+        validate(&[]).unwrap();
+        assert!(!dir.join("queue.json").exists());
+
+        let error = validate(&["--events", &path("events.jsonl")]).unwrap_err();
+        assert_eq!(error.exit_code(), 2);
+        assert!(!dir.join("events.jsonl").exists());
+    }
+
+    #[test]
+    /// approved transactions reach the accounts when the input is processed again with the queue
+    fn review_workflow() {
+        let dir = TempDir::new("cli-workflow");
+        let path = |name: &str| dir.arg(name);
+
+        fs::write(path("config.toml"), "[[risk]]\nname = \"large\"\nrule = \"amount\"\nabove = 100.0\naction = \"hold\"\n").unwrap();
+        fs::write(path("transactions.csv"), "type,client,tx,amount\ndeposit,1,1,500.0\nwithdrawal,1,2,50.0\ndeposit,1,3,20.0\n").unwrap();
+
+        let process = || {
+            let args = Args::try_parse_from([
+                "payments-engine",
+                "--config",
+                &path("config.toml"),
+                "--review-queue",
+                &path("queue.json"),
+                "--output",
+                &path("accounts.csv"),
+                &path("transactions.csv"),
+            ])
+            .unwrap();
+            run(args).unwrap();
+            fs::read_to_string(path("accounts.csv")).unwrap()
+        };
+        let review = |command: &str| {
+            let args = Args::try_parse_from(["payments-engine", "review", command, "1", "--review-queue", &path("queue.json")]).unwrap();
+            run(args).unwrap();
+        };
+
+        assert_eq!(process(), "client,available,held,total,locked\n1,20.0,0.0,20.0,false\n");
+
+        // the decision alone leaves accounts as they are
+        review("approve");
+        assert_eq!(fs::read_to_string(path("accounts.csv")).unwrap(), "client,available,held,total,locked\n1,20.0,0.0,20.0,false\n");
+
+        // the approved deposit is applied before the withdrawal it funds
+        assert_eq!(process(), "client,available,held,total,locked\n1,470.0,0.0,470.0,false\n");
+        assert_eq!(ReviewQueue::load(path("queue.json")).unwrap().held()[0].status, Status::Approved);
+    }
+}
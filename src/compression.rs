@@ -0,0 +1,201 @@
+use bzip2::read::MultiBzDecoder;
+use bzip2::write::BzEncoder;
+use clap::ValueEnum;
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use std::io::{self, BufReader, Chain, Cursor, Read, Write};
+use std::path::Path;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+const BZIP2_MAGIC: &[u8] = b"BZh";
+
+/// Compression formats of input and output
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub(crate) enum Compression {
+    None,
+    Gzip,
+    Zstd,
+    Bzip2,
+}
+
+impl Compression {
+    /// Compression implied by a file extension like `.gz`, `.zst` or `.bz2`
+    pub(crate) fn from_extension(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("gz") => Compression::Gzip,
+            Some("zst") => Compression::Zstd,
+            Some("bz2") => Compression::Bzip2,
+            _ => Compression::None,
+        }
+    }
+
+    /// Detect compression by the magic bytes at the start of a stream
+    fn detect(head: &[u8]) -> Self {
+        if head.starts_with(GZIP_MAGIC) {
+            Compression::Gzip
+        } else if head.starts_with(ZSTD_MAGIC) {
+            Compression::Zstd
+        } else if head.starts_with(BZIP2_MAGIC) {
+            Compression::Bzip2
+        } else {
+            Compression::None
+        }
+    }
+}
+
+/// Inner reader behind the bytes read to detect its compression
+type Peeked<R> = BufReader<Chain<Cursor<Vec<u8>>, R>>;
+
+/// Reader that decompresses its inner reader if it starts with known magic bytes
+pub(crate) enum Decoder<R: Read> {
+    Plain(Peeked<R>),
+    Gzip(MultiGzDecoder<Peeked<R>>),
+    Zstd(zstd::Decoder<'static, Peeked<R>>),
+    Bzip2(MultiBzDecoder<Peeked<R>>),
+}
+
+impl<R: Read> Decoder<R> {
+    /// Read the first bytes of reader to choose a decompressor, fallback applies when none of the magic bytes match
+    pub(crate) fn new(mut rdr: R, fallback: Compression) -> io::Result<Self> {
+        // a pipe may hand over fewer bytes than the longest magic at a time
+        let mut head = Vec::with_capacity(ZSTD_MAGIC.len());
+        rdr.by_ref().take(ZSTD_MAGIC.len() as u64).read_to_end(&mut head)?;
+
+        let compression = match Compression::detect(&head) {
+            Compression::None => fallback,
+            detected => detected,
+        };
+
+        let rdr = BufReader::new(Cursor::new(head).chain(rdr));
+        Ok(match compression {
+            Compression::None => Decoder::Plain(rdr),
+            Compression::Gzip => Decoder::Gzip(MultiGzDecoder::new(rdr)),
+            Compression::Zstd => Decoder::Zstd(zstd::Decoder::with_buffer(rdr)?),
+            Compression::Bzip2 => Decoder::Bzip2(MultiBzDecoder::new(rdr)),
+        })
+    }
+}
+
+impl<R: Read> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Decoder::Plain(r) => r.read(buf),
+            Decoder::Gzip(r) => r.read(buf),
+            Decoder::Zstd(r) => r.read(buf),
+            Decoder::Bzip2(r) => r.read(buf),
+        }
+    }
+}
+
+/// Writer that compresses into its inner writer, must be finished to write the trailer
+pub(crate) enum Encoder<W: Write> {
+    Plain(W),
+    Gzip(GzEncoder<W>),
+    Zstd(zstd::Encoder<'static, W>),
+    Bzip2(BzEncoder<W>),
+}
+
+impl<W: Write> Encoder<W> {
+    pub(crate) fn new(wtr: W, compression: Compression) -> io::Result<Self> {
+        Ok(match compression {
+            Compression::None => Encoder::Plain(wtr),
+            Compression::Gzip => Encoder::Gzip(GzEncoder::new(wtr, flate2::Compression::default())),
+            Compression::Zstd => Encoder::Zstd(zstd::Encoder::new(wtr, zstd::DEFAULT_COMPRESSION_LEVEL)?),
+            Compression::Bzip2 => Encoder::Bzip2(BzEncoder::new(wtr, bzip2::Compression::default())),
+        })
+    }
+
+    /// Write the end of the compressed stream and return the inner writer flushed
+    pub(crate) fn finish(self) -> io::Result<W> {
+        let mut wtr = match self {
+            Encoder::Plain(w) => w,
+            Encoder::Gzip(w) => w.finish()?,
+            Encoder::Zstd(w) => w.finish()?,
+            Encoder::Bzip2(w) => w.finish()?,
+        };
+        wtr.flush()?;
+        Ok(wtr)
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Encoder::Plain(w) => w.write(buf),
+            Encoder::Gzip(w) => w.write(buf),
+            Encoder::Zstd(w) => w.write(buf),
+            Encoder::Bzip2(w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Encoder::Plain(w) => w.flush(),
+            Encoder::Gzip(w) => w.flush(),
+            Encoder::Zstd(w) => w.flush(),
+            Encoder::Bzip2(w) => w.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EngineConfig;
+    use crate::engine::Engine;
+
+    #[test]
+    /// engine reads every compression transparently and output decompresses back
+    fn round_trip() {
+        let csv = "\
+type,       client, tx, amount
+deposit,    1,      1,  1.0
+";
+
+        for compression in [Compression::None, Compression::Gzip, Compression::Zstd, Compression::Bzip2] {
+            let mut input = Encoder::new(vec![], compression).unwrap();
+            input.write_all(csv.as_bytes()).unwrap();
+            let input = input.finish().unwrap();
+
+            let mut engine = Engine::new(EngineConfig::default());
+            engine.input(input.as_slice()).unwrap();
+
+            let mut output = Encoder::new(vec![], compression).unwrap();
+            engine.output(&mut output).unwrap();
+            let output = output.finish().unwrap();
+
+            let mut accounts = String::new();
+            Decoder::new(output.as_slice(), Compression::None).unwrap().read_to_string(&mut accounts).unwrap();
+            assert_eq!(accounts, "client,available,held,total,locked\n1,1.0,0.0,1.0,false\n", "{compression:?}");
+        }
+    }
+
+    /// Reader handing over a byte at a time like a slow pipe
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.0.take(1).read(buf).inspect(|n| self.0 = &self.0[*n..])
+        }
+    }
+
+    #[test]
+    /// magic bytes arriving in pieces are detected, the extension decides when none match
+    fn detection() {
+        let mut input = Encoder::new(vec![], Compression::Zstd).unwrap();
+        input.write_all(b"type,client,tx,amount\n").unwrap();
+        let input = input.finish().unwrap();
+
+        let mut csv = String::new();
+        Decoder::new(Trickle(&input), Compression::None).unwrap().read_to_string(&mut csv).unwrap();
+        assert_eq!(csv, "type,client,tx,amount\n");
+
+        let mut csv = String::new();
+        Decoder::new(&b"a,b"[..], Compression::None).unwrap().read_to_string(&mut csv).unwrap();
+        assert_eq!(csv, "a,b");
+
+        // a .gz file that is not gzip fails instead of being read as csv
+        assert!(Decoder::new(&b"a,b"[..], Compression::Gzip).unwrap().read_to_string(&mut csv).is_err());
+    }
+}
@@ -0,0 +1,235 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::credit::{self, CreditLine};
+use crate::dispute::DisputePolicy;
+use crate::fee::Fees;
+use crate::handler;
+use crate::interest::Interest;
+use crate::limit::Limits;
+use crate::risk::{self, Rule};
+use crate::transaction::{AccountId, Money, Timestamp, TransactionType};
+use crate::webhook::Webhook;
+
+/// Most decimal places amounts can be rounded to and still be represented exactly enough by `Money`
+const MAX_AMOUNT_PLACES: u32 = 8;
+
+/// Policies of applying transactions to accounts
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EngineConfig {
+    /// Types of transactions that can be disputed, `deposit` and `withdrawal`
+    pub(crate) disputable: Vec<TransactionType>,
+    /// Chargeback locks the account
+    pub(crate) lock_on_chargeback: bool,
+    /// Types of transactions a locked account still accepts, by default those settling open disputes
+    pub(crate) locked_accepts: Vec<TransactionType>,
+    /// Round input amounts half away from zero to this many decimal places, amounts are kept as given if omitted
+    pub(crate) amount_places: Option<u32>,
+    /// Fees charged to clients and booked to an internal account
+    pub(crate) fees: Fees,
+    /// Deposit and withdrawal limits of clients
+    pub(crate) limits: Limits,
+    /// Overdrafts approved to clients
+    pub(crate) credit: Vec<CreditLine>,
+    /// Interest on available funds booked by accrual
+    pub(crate) interest: Interest,
+    /// Risk rules evaluated on every transaction before it is applied
+    pub(crate) risk: Vec<Rule>,
+    /// Dispute activity an account is locked or flagged for
+    pub(crate) disputes: DisputePolicy,
+    /// Custom transaction types bundled with the engine to accept from input
+    pub(crate) custom_types: Vec<String>,
+    /// Manual debits may take available funds below zero and beyond credit lines
+    pub(crate) adjustments_bypass_checks: bool,
+    /// Endpoints notified of account events in serve mode
+    pub(crate) webhooks: Vec<Webhook>,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        Self {
+            disputable: vec![TransactionType::Deposit],
+            lock_on_chargeback: true,
+            locked_accepts: vec![TransactionType::Resolve, TransactionType::Chargeback],
+            amount_places: None,
+            fees: Fees::default(),
+            limits: Limits::default(),
+            credit: Vec::new(),
+            interest: Interest::default(),
+            risk: Vec::new(),
+            disputes: DisputePolicy::default(),
+            custom_types: Vec::new(),
+            adjustments_bypass_checks: false,
+            webhooks: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("cannot read config: {0}")]
+    Read(#[from] std::io::Error),
+
+    #[error("cannot parse config: {0}")]
+    Parse(#[from] toml::de::Error),
+
+    #[error("invalid config: {0}")]
+    Invalid(String),
+}
+
+impl EngineConfig {
+    /// Load config from a toml file, omitted settings keep their defaults
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let config: EngineConfig = toml::from_str(&fs::read_to_string(path)?)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Replace limits with the ones of a toml file laid out as the `limits` section
+    pub(crate) fn load_limits<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Error> {
+        let limits: Limits = toml::from_str(&fs::read_to_string(path)?)?;
+        limits.validate().map_err(|e| Error::Invalid(format!("limits: {e}")))?;
+        self.limits = limits;
+        Ok(())
+    }
+
+    /// Defaults as a commented toml document to start a config file from
+    pub(crate) fn defaults_toml() -> String {
+        let toml = toml::to_string(&Self::default()).expect("default config serializes to toml");
+
+        // optional settings go before the first table to stay top level when uncommented
+        let tables = toml.find("\n[").map_or(toml.len(), |i| i + 1);
+        format!("{}# amount_places = 4\n{}", &toml[..tables], &toml[tables..])
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        if let Some(t) = self
+            .disputable
+            .iter()
+            .find(|t| !matches!(t, TransactionType::Deposit | TransactionType::Withdrawal))
+        {
+            return Err(Error::Invalid(format!("disputable: {t:?} transactions cannot be disputed")));
+        }
+
+        if let Some(places) = self.amount_places.filter(|places| *places > MAX_AMOUNT_PLACES) {
+            return Err(Error::Invalid(format!("amount_places: {places} is more than {MAX_AMOUNT_PLACES}")));
+        }
+
+        self.fees.validate().map_err(|e| Error::Invalid(format!("fees: {e}")))?;
+        self.limits.validate().map_err(|e| Error::Invalid(format!("limits: {e}")))?;
+        credit::validate(&self.credit).map_err(|e| Error::Invalid(format!("credit: {e}")))?;
+        self.interest.validate().map_err(|e| Error::Invalid(format!("interest: {e}")))?;
+        risk::validate(&self.risk).map_err(|e| Error::Invalid(format!("risk: {e}")))?;
+        self.disputes.validate().map_err(|e| Error::Invalid(format!("disputes: {e}")))?;
+
+        if let Some(name) = self.custom_types.iter().find(|name| handler::bundled(name).is_none()) {
+            return Err(Error::Invalid(format!("custom_types: {name} is not one of {}", handler::BUNDLED.join(", "))));
+        }
+
+        for webhook in self.webhooks.iter() {
+            webhook.validate().map_err(|e| Error::Invalid(format!("webhooks: {e}")))?;
+        }
+
+        if let Some(line) = self.credit.iter().find(|line| self.is_internal(line.client)) {
+            return Err(Error::Invalid(format!("credit: internal account {} cannot have a credit line", line.client)));
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn is_disputable(&self, transaction_type: TransactionType) -> bool {
+        self.disputable.contains(&transaction_type)
+    }
+
+    pub(crate) fn is_accepted_when_locked(&self, transaction_type: TransactionType) -> bool {
+        self.locked_accepts.contains(&transaction_type)
+    }
+
+    /// Fee charged to client for a transaction of amount, rounded as amounts
+    pub(crate) fn fee(&self, client: AccountId, transaction_type: TransactionType, amount: Money) -> Money {
+        self.round(self.fees.fee(client, transaction_type, amount))
+    }
+
+    /// Internal account fees are booked to
+    pub(crate) fn is_internal(&self, account_id: AccountId) -> bool {
+        self.fees.account == Some(account_id)
+    }
+
+    /// Credit line of client if it has one
+    pub(crate) fn credit_line(&self, client: AccountId) -> Option<&CreditLine> {
+        self.credit.iter().find(|line| line.client == client)
+    }
+
+    /// Overdraft limit of client, zero without a credit line
+    pub(crate) fn credit_limit(&self, client: AccountId) -> Money {
+        self.credit_line(client).map_or(0.0, |line| line.limit)
+    }
+
+    /// Interest of client on balance held for secs, overdrafts are charged at the rate of the credit line,
+    /// nothing on an interest-free one, and at the overdraft rate without a line
+    pub(crate) fn interest(&self, client: AccountId, balance: Money, secs: Timestamp) -> Money {
+        let rate = if balance >= 0.0 {
+            self.interest.rate
+        } else {
+            self.credit_line(client).map_or(self.interest.overdraft_rate, |line| line.rate.unwrap_or(0.0))
+        };
+
+        self.interest.accrued(balance, rate, secs)
+    }
+
+    /// Amount rounded to the configured decimal places
+    pub(crate) fn round(&self, amount: Money) -> Money {
+        match self.amount_places {
+            Some(places) => {
+                let factor = 10f64.powi(places as i32);
+                (amount * factor).round() / factor
+            }
+            None => amount,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tempdir::TempDir;
+
+    #[test]
+    /// printed defaults parse back into defaults and invalid policies are refused
+    fn defaults_and_validation() {
+        let defaults: EngineConfig = toml::from_str(&EngineConfig::defaults_toml()).unwrap();
+        assert_eq!(defaults, EngineConfig::default());
+        assert!(defaults.validate().is_ok());
+
+        let config: EngineConfig = toml::from_str(r#"disputable = ["deposit", "chargeback"]"#).unwrap();
+        assert!(matches!(config.validate(), Err(Error::Invalid(..))));
+
+        let config: EngineConfig = toml::from_str("amount_places = 9").unwrap();
+        assert!(matches!(config.validate(), Err(Error::Invalid(..))));
+
+        let config: EngineConfig = toml::from_str("amount_places = 2").unwrap();
+        assert_eq!(config.round(1.005001), 1.01);
+        assert_eq!(config.round(-0.125), -0.13);
+
+        assert!(toml::from_str::<EngineConfig>("lock_on_dispute = true").is_err());
+    }
+
+    #[test]
+    /// limits file replaces the limits section and is validated like it
+    fn limits_file() {
+        let dir = TempDir::new("limits");
+        let path = dir.join("limits.toml");
+
+        let mut config: EngineConfig = toml::from_str("[limits]\ndeposit = { per_transaction = 10.0 }").unwrap();
+        fs::write(&path, "withdrawal = { per_period = 50.0 }\n[[clients]]\nclient = 7\ndeposit = { per_transaction = 5.0 }\n").unwrap();
+        config.load_limits(&path).unwrap();
+        assert_eq!(config.limits.deposit.per_transaction, None);
+        assert_eq!(config.limits.withdrawal.per_period, Some(50.0));
+        assert_eq!(config.limits.clients[0].client, 7);
+
+        fs::write(&path, "period_secs = 0").unwrap();
+        assert!(matches!(config.load_limits(&path), Err(Error::Invalid(..))));
+    }
+}
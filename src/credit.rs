@@ -0,0 +1,61 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+use crate::transaction::{AccountId, Money};
+
+/// Approved overdraft of a client letting its available funds go negative down to minus the limit
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct CreditLine {
+    pub(crate) client: AccountId,
+    pub(crate) limit: Money,
+    /// Annual interest rate in percent on credit used, the line is interest-free if omitted
+    pub(crate) rate: Option<f64>,
+}
+
+/// Credit lines are valid when every client has one line at most with non-negative limit and rate
+pub(crate) fn validate(lines: &[CreditLine]) -> Result<(), String> {
+    let mut clients = BTreeSet::new();
+
+    for line in lines {
+        if !clients.insert(line.client) {
+            return Err(format!("client {} has more than one credit line", line.client));
+        }
+
+        let negative = |v: f64| v.is_nan() || v < 0.0;
+        if negative(line.limit) || line.rate.is_some_and(negative) {
+            return Err(format!("client {}: limit and rate must not be negative", line.client));
+        }
+    }
+
+    Ok(())
+}
+
+/// Credit used by an account with available funds and what is left of its limit
+pub(crate) fn usage(available: Money, limit: Money) -> (Money, Money) {
+    let used = (-available).max(0.0);
+    (used, (limit - used).max(0.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// one line per client, used credit grows as available funds go negative
+    fn credit_lines() {
+        let lines = [
+            CreditLine { client: 1, limit: 100.0, rate: None },
+            CreditLine { client: 2, limit: 50.0, rate: Some(12.5) },
+        ];
+
+        assert!(validate(&lines).is_ok());
+
+        assert!(validate(&[lines[0].clone(), lines[0].clone()]).is_err());
+        assert!(validate(&[CreditLine { client: 1, limit: -1.0, rate: None }]).is_err());
+
+        assert_eq!(usage(10.0, 100.0), (0.0, 100.0));
+        assert_eq!(usage(-30.0, 100.0), (30.0, 70.0));
+        assert_eq!(usage(-30.0, 0.0), (30.0, 0.0));
+    }
+}
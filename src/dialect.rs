@@ -0,0 +1,270 @@
+use csv::{ByteRecord, Reader, ReaderBuilder, StringRecord, Trim, Writer, WriterBuilder};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+
+/// Engine names of input columns in the default order, used for files without a header row
+pub(crate) const INPUT_COLUMNS: [&str; 5] = ["type", "client", "tx", "amount", "timestamp"];
+
+/// Engine names of input and output columns with amounts
+const AMOUNT_COLUMNS: [&str; 6] = ["amount", "available", "held", "total", "credit_used", "credit_remaining"];
+
+/// Csv dialect of a partner file or of the output, defaults to the engine's own format
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Dialect {
+    pub delimiter: char,
+    pub quote: char,
+    /// First row holds column names
+    pub has_headers: bool,
+    /// Column names in file order when there is no header row, engine names before mapping
+    pub headers: Vec<String>,
+    /// Engine column name to the column name in the file, e.g. `type = "kind"`
+    pub columns: BTreeMap<String, String>,
+    pub decimal_separator: char,
+}
+
+impl Default for Dialect {
+    fn default() -> Self {
+        Self {
+            delimiter: ',',
+            quote: '"',
+            has_headers: true,
+            headers: INPUT_COLUMNS.iter().map(|c| c.to_string()).collect(),
+            columns: BTreeMap::new(),
+            decimal_separator: '.',
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("cannot read dialect: {0}")]
+    Read(#[from] std::io::Error),
+
+    #[error("cannot parse dialect: {0}")]
+    Parse(#[from] toml::de::Error),
+
+    #[error("{0} must be a single ascii character")]
+    NotAscii(&'static str),
+}
+
+impl Dialect {
+    /// Load dialect from a toml file, omitted settings keep their defaults
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let dialect: Dialect = toml::from_str(&fs::read_to_string(path)?)?;
+        dialect.validate()?;
+        Ok(dialect)
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        for (name, c) in [("delimiter", self.delimiter), ("quote", self.quote), ("decimal_separator", self.decimal_separator)] {
+            if !c.is_ascii() {
+                return Err(Error::NotAscii(name));
+            }
+        }
+        Ok(())
+    }
+
+    /// Name of an engine column in the file
+    fn file_column<'a>(&'a self, column: &'a str) -> &'a str {
+        self.columns.get(column).map_or(column, String::as_str)
+    }
+
+    /// Name of a file column in the engine
+    fn engine_column<'a>(&'a self, column: &'a str) -> &'a str {
+        self.columns
+            .iter()
+            .find(|(_, file)| file.as_str() == column)
+            .map_or(column, |(engine, _)| engine.as_str())
+    }
+
+    pub(crate) fn reader<R: Read>(&self, rdr: R) -> Reader<R> {
+        ReaderBuilder::new()
+            .delimiter(self.delimiter as u8)
+            .quote(self.quote as u8)
+            .has_headers(self.has_headers)
+            .trim(Trim::All) // trim leading and trailing whitespace
+            .flexible(true) // allow for missing columns like amount
+            .from_reader(rdr)
+    }
+
+    /// Column names of the records in engine terms, from the header row or configured
+    pub(crate) fn input_headers<R: Read>(&self, reader: &mut Reader<R>) -> csv::Result<StringRecord> {
+        if !self.has_headers {
+            return Ok(self.headers.iter().collect());
+        }
+        Ok(reader.headers()?.iter().map(|h| self.engine_column(h)).collect())
+    }
+
+    /// Deserialize a record with engine column names, converting the decimal separator of amounts
+    pub(crate) fn deserialize<T: DeserializeOwned>(&self, record: StringRecord, headers: &StringRecord) -> csv::Result<T> {
+        if self.decimal_separator == '.' {
+            return record.deserialize(Some(headers));
+        }
+
+        let mut converted: StringRecord = record
+            .iter()
+            .zip(headers.iter().map(Some).chain(std::iter::repeat(None)))
+            .map(|(field, header)| match header {
+                Some(h) if AMOUNT_COLUMNS.contains(&h) => field.replace(self.decimal_separator, "."),
+                _ => field.to_string(),
+            })
+            .collect();
+        converted.set_position(record.position().cloned());
+
+        converted.deserialize(Some(headers))
+    }
+
+    /// Writer in this dialect with the header row written if the dialect has one
+    pub(crate) fn writer<W: Write>(&self, wtr: W, columns: &[&str]) -> csv::Result<Writer<W>> {
+        let mut writer = WriterBuilder::new()
+            .delimiter(self.delimiter as u8)
+            .quote(self.quote as u8)
+            .has_headers(false)
+            .from_writer(wtr);
+
+        if self.has_headers {
+            writer.write_record(columns.iter().map(|c| self.file_column(c)))?;
+        }
+
+        Ok(writer)
+    }
+
+    /// Serialize a record with amounts converted to the decimal separator of the dialect
+    pub(crate) fn serialize<W: Write, T: Serialize>(&self, writer: &mut Writer<W>, columns: &[&str], value: &T) -> csv::Result<()> {
+        if self.decimal_separator == '.' {
+            return writer.serialize(value);
+        }
+
+        // serialize into a plain record first to get the fields as the engine formats them
+        let mut plain = WriterBuilder::new().has_headers(false).from_writer(vec![]);
+        plain.serialize(value)?;
+        let bytes = plain.into_inner().map_err(|e| e.into_error())?;
+
+        let mut record = ByteRecord::new();
+        ReaderBuilder::new().has_headers(false).from_reader(bytes.as_slice()).read_byte_record(&mut record)?;
+
+        let separator = self.decimal_separator.to_string();
+        let fields = record.iter().zip(columns).map(|(field, column)| {
+            let field = String::from_utf8_lossy(field);
+            if AMOUNT_COLUMNS.contains(column) {
+                field.replace('.', &separator)
+            } else {
+                field.into_owned()
+            }
+        });
+
+        writer.write_record(fields)
+    }
+}
+
+/// Dialects of inputs: per file glob patterns checked in order and the default for the rest
+#[derive(Debug, Default)]
+pub(crate) struct Dialects {
+    pub(crate) default: Dialect,
+    pub(crate) per_file: Vec<(glob::Pattern, Dialect)>,
+}
+
+impl Dialects {
+    /// Dialect of the first pattern matching the input name or the default one
+    pub(crate) fn for_input(&self, name: &str) -> &Dialect {
+        self.per_file
+            .iter()
+            .find(|(pattern, _)| pattern.matches(name))
+            .map_or(&self.default, |(_, dialect)| dialect)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EngineConfig;
+    use crate::engine::Engine;
+
+    #[test]
+    /// headerless semicolon file with decimal comma in, renamed columns and decimal comma out
+    fn partner_dialect() {
+        let input: Dialect = toml::from_str(
+            r#"
+delimiter = ";"
+has_headers = false
+headers = ["client", "tx", "type", "amount"]
+decimal_separator = ","
+"#,
+        )
+        .unwrap();
+
+        let csv = "\
+1; 1; deposit;    1,5
+1; 2; withdrawal; 0,25
+";
+
+        let mut engine = Engine::new(EngineConfig::default());
+        engine.input_dialect(csv.as_bytes(), &input).unwrap();
+
+        let output: Dialect = toml::from_str(
+            r#"
+delimiter = ";"
+decimal_separator = ","
+
+[columns]
+client = "client_id"
+locked = "frozen"
+"#,
+        )
+        .unwrap();
+
+        let mut out = vec![];
+        engine.output_dialect(&mut out, &output).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\
+client_id;available;held;total;frozen
+1;1,25;0,0;1,25;false
+"
+        );
+    }
+
+    #[test]
+    /// file column names are mapped to the engine ones
+    fn renamed_columns() {
+        let dialect: Dialect = toml::from_str(
+            r#"
+[columns]
+type = "kind"
+client = "client_id"
+"#,
+        )
+        .unwrap();
+
+        let csv = "\
+kind,    client_id, tx, amount
+deposit,         7,  1,    2.0
+";
+
+        let mut engine = Engine::new(EngineConfig::default());
+        engine.input_dialect(csv.as_bytes(), &dialect).unwrap();
+        assert_eq!(engine.account(7).unwrap().total.0, 2.0);
+    }
+
+    #[test]
+    fn per_file() {
+        let partner = Dialect {
+            delimiter: ';',
+            ..Dialect::default()
+        };
+        let dialects = Dialects {
+            default: Dialect::default(),
+            per_file: vec![(glob::Pattern::new("partner/*.csv").unwrap(), partner.clone())],
+        };
+
+        assert_eq!(dialects.for_input("partner/2023-09-01.csv"), &partner);
+        assert_eq!(dialects.for_input("own/2023-09-01.csv"), &Dialect::default());
+        assert!(toml::from_str::<Dialect>("separator = ';'").is_err());
+    }
+}
@@ -0,0 +1,136 @@
+use serde::{Deserialize, Serialize};
+
+use crate::transaction::{Money, TransactionType};
+
+/// What happens to an account exceeding a dispute threshold
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum DisputeAction {
+    /// Lock the account as a chargeback does
+    Lock,
+    /// Keep the account open and only record the reason on it
+    Flag,
+}
+
+/// Thresholds of dispute activity an account is locked or flagged above, omitted ones are unlimited
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct DisputePolicy {
+    /// Most disputes open at the same time
+    pub(crate) max_open: Option<usize>,
+    /// Largest sum of amounts under open disputes
+    pub(crate) max_open_value: Option<Money>,
+    /// Largest ratio of disputes ever raised to deposits
+    pub(crate) max_ratio: Option<f64>,
+    /// Deposits an account needs before its ratio counts
+    pub(crate) min_deposits: usize,
+    pub(crate) action: DisputeAction,
+}
+
+impl Default for DisputePolicy {
+    fn default() -> Self {
+        Self {
+            max_open: None,
+            max_open_value: None,
+            max_ratio: None,
+            min_deposits: 0,
+            action: DisputeAction::Lock,
+        }
+    }
+}
+
+impl DisputePolicy {
+    /// Reason the activity exceeds a threshold if it does
+    pub(crate) fn exceeded(&self, activity: &DisputeActivity) -> Option<String> {
+        if let Some(max) = self.max_open.filter(|max| activity.open > *max) {
+            return Some(format!("{} open disputes exceed {max}", activity.open));
+        }
+
+        if let Some(max) = self.max_open_value.filter(|max| activity.open_value > *max) {
+            return Some(format!("open disputes of {} exceed {max}", activity.open_value));
+        }
+
+        if activity.deposits > 0 && activity.deposits >= self.min_deposits {
+            let ratio = activity.disputes as f64 / activity.deposits as f64;
+            if let Some(max) = self.max_ratio.filter(|max| ratio > *max) {
+                return Some(format!("{} disputes of {} deposits exceed ratio {max}", activity.disputes, activity.deposits));
+            }
+        }
+
+        None
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if [self.max_open_value, self.max_ratio].into_iter().flatten().any(|max| max.is_nan() || max < 0.0) {
+            return Err("thresholds must not be negative".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Dispute activity of an account
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct DisputeActivity {
+    pub(crate) open: usize,
+    pub(crate) open_value: Money,
+    /// Disputes ever raised
+    pub(crate) disputes: usize,
+    pub(crate) deposits: usize,
+}
+
+impl DisputeActivity {
+    /// Account for a transaction of amount applied to the account
+    pub(crate) fn add(&mut self, transaction_type: TransactionType, amount: Money) {
+        match transaction_type {
+            TransactionType::Deposit => self.deposits += 1,
+            TransactionType::Dispute => {
+                self.open += 1;
+                self.open_value += amount;
+                self.disputes += 1;
+            }
+            TransactionType::Resolve | TransactionType::Chargeback => {
+                self.open = self.open.saturating_sub(1);
+                self.open_value -= amount;
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// every threshold is exceeded by the activity it describes, the ratio only once there are enough deposits
+    fn thresholds() {
+        let policy = DisputePolicy {
+            max_open: Some(1),
+            max_open_value: Some(100.0),
+            max_ratio: Some(0.5),
+            min_deposits: 3,
+            ..DisputePolicy::default()
+        };
+        assert!(policy.validate().is_ok());
+
+        let mut activity = DisputeActivity::default();
+        activity.add(TransactionType::Deposit, 10.0);
+        activity.add(TransactionType::Deposit, 200.0);
+        activity.add(TransactionType::Dispute, 10.0);
+        assert_eq!(policy.exceeded(&activity), None);
+
+        activity.add(TransactionType::Dispute, 200.0);
+        assert_eq!(policy.exceeded(&activity).unwrap(), "2 open disputes exceed 1");
+
+        activity.add(TransactionType::Resolve, 10.0);
+        assert_eq!(policy.exceeded(&activity).unwrap(), "open disputes of 200 exceed 100");
+
+        activity.add(TransactionType::Chargeback, 200.0);
+        assert_eq!(policy.exceeded(&activity), None);
+
+        activity.add(TransactionType::Deposit, 10.0);
+        assert_eq!(policy.exceeded(&activity).unwrap(), "2 disputes of 3 deposits exceed ratio 0.5");
+
+        assert!(DisputePolicy { max_ratio: Some(-1.0), ..DisputePolicy::default() }.validate().is_err());
+    }
+}
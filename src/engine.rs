@@ -1,83 +1,483 @@
-use csv::{Error, ReaderBuilder, Trim, WriterBuilder};
+use csv::{DeserializeErrorKind, ErrorKind};
 use std::collections::HashMap;
-use std::io::{Read, Write};
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tracing::{debug, field, span, warn, Level, Span};
 
-use crate::account::Account;
-use crate::transaction::{AccountId, Transaction};
+use crate::account::{self, Account};
+use crate::compression::{Compression, Decoder};
+use crate::config::EngineConfig;
+use crate::dialect::Dialect;
+use crate::event::{EventKind, EventSink, EventStream};
+use crate::handler::{self, Handlers, TransactionHandler};
+use crate::metrics::Metrics;
+use crate::review::{ReviewQueue, Status};
+use crate::risk::{Action, Hit, Risk};
+use crate::script::{self, Scripts};
+use crate::stats::Stats;
+use crate::transaction::{AccountId, Timestamp, Transaction, TransactionId, TransactionType, SYSTEM_TX_START};
+
+/// Input record that is ignored because it cannot be parsed or applied to its account
+#[derive(Debug, Error)]
+pub enum Rejection {
+    #[error("cannot parse transaction for {0}")]
+    Parse(csv::Error),
+
+    #[error("cannot parse transaction of type {0} without a handler registered")]
+    UnknownType(TransactionType),
+
+    #[error("cannot apply transaction for {1}")]
+    Apply(TransactionType, account::Error),
+
+    #[error("transaction is stopped by {1}")]
+    Risk(TransactionType, Hit),
+
+    #[error("transaction {1} is declined in review")]
+    Declined(TransactionType, TransactionId),
+
+    #[error("transaction is rejected by {1}")]
+    Script(TransactionType, script::Rejected),
+}
+
+impl Rejection {
+    /// Name of the reason for grouping rejections: csv error kind or account error variant
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Rejection::Parse(error) => match error.kind() {
+                ErrorKind::Io(..) => "Io",
+                ErrorKind::Utf8 { .. } => "Utf8",
+                ErrorKind::UnequalLengths { .. } => "UnequalLengths",
+                ErrorKind::Deserialize { err, .. } => match err.kind() {
+                    DeserializeErrorKind::Message(..) => "InvalidValue",
+                    DeserializeErrorKind::Unsupported(..) => "Unsupported",
+                    DeserializeErrorKind::UnexpectedEndOfRow => "UnexpectedEndOfRow",
+                    DeserializeErrorKind::InvalidUtf8(..) => "InvalidUtf8",
+                    DeserializeErrorKind::ParseBool(..) => "ParseBool",
+                    DeserializeErrorKind::ParseInt(..) => "ParseInt",
+                    DeserializeErrorKind::ParseFloat(..) => "ParseFloat",
+                },
+                _ => "Other",
+            },
+            // as an unknown variant of a built-in type
+            Rejection::UnknownType(..) => "InvalidValue",
+            Rejection::Apply(_, error) => error.kind(),
+            Rejection::Risk(..) => "RiskRule",
+            Rejection::Declined(..) => "Declined",
+            Rejection::Script(..) => "Script",
+        }
+    }
+
+    /// Stage at which the record is rejected
+    pub fn stage(&self) -> &'static str {
+        match self {
+            Rejection::Parse(..) | Rejection::UnknownType(..) => "parse",
+            Rejection::Apply(..) => "apply",
+            Rejection::Risk(..) => "risk",
+            Rejection::Declined(..) => "review",
+            Rejection::Script(..) => "script",
+        }
+    }
+}
+
+/// Errors that stop processing
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("cannot read input: {0}")]
+    Input(csv::Error),
+
+    #[error("record {record} is rejected: {rejection}")]
+    Rejected { record: usize, rejection: Rejection },
+
+    #[error("cannot write accounts: {0}")]
+    Output(csv::Error),
+
+    #[error("cannot write events: {0}")]
+    Events(io::Error),
+}
 
 /// Takes transactions as reader input, processes them and outputs accounts with aggregate values
 pub struct Engine {
     /// Store accounts in memory for look up by id
     account_map: HashMap<AccountId, Account>,
+    /// Counters collected while processing input
+    stats: Stats,
+    /// Live metrics for long running mode
+    metrics: Option<Arc<Metrics>>,
+    /// Stop at the first rejected record instead of ignoring it
+    fail_fast: bool,
+    /// Policies of applying transactions to accounts
+    config: EngineConfig,
+    /// Id of the next transaction generated by the engine
+    next_system_tx: TransactionId,
+    /// Point in time interest of all accounts is booked until
+    accrued_until: Option<Timestamp>,
+    /// Client activity risk rules are evaluated against
+    risk: Risk,
+    /// Transactions held by risk rules and decisions on them
+    review: ReviewQueue,
+    /// Custom validation scripts run before risk rules
+    scripts: Option<Scripts>,
+    /// Handlers of custom transaction types
+    handlers: Handlers,
+    /// Changes of accounts emitted to sinks
+    events: EventStream,
 }
 
 impl Engine {
-    pub(crate) fn new() -> Self {
-        Self {
+    /// Engine applying transactions under config, custom types of a config that was not validated
+    /// are skipped with a warning unless they are bundled
+    pub fn new(config: EngineConfig) -> Self {
+        let mut engine = Self {
             account_map: HashMap::new(),
+            stats: Stats::default(),
+            metrics: None,
+            fail_fast: false,
+            config,
+            next_system_tx: SYSTEM_TX_START,
+            accrued_until: None,
+            risk: Risk::default(),
+            review: ReviewQueue::default(),
+            scripts: None,
+            handlers: Handlers::default(),
+            events: EventStream::default(),
+        };
+
+        for name in engine.config.custom_types.clone() {
+            match handler::bundled(&name) {
+                Some(handler) => {
+                    engine.register(&name, handler).expect("bundled custom types have no built-in names");
+                }
+                None => warn!(custom_type = %name, "custom type is not bundled, skipped"),
+            }
         }
+
+        engine
+    }
+
+    /// Publish live metrics of every processed record
+    pub(crate) fn set_metrics(&mut self, metrics: Arc<Metrics>) {
+        self.metrics = Some(metrics);
+    }
+
+    /// Make input fail with the first record that cannot be parsed or applied
+    pub(crate) fn set_fail_fast(&mut self, fail_fast: bool) {
+        self.fail_fast = fail_fast;
+    }
+
+    /// Register a handler of a custom transaction type read from input by name,
+    /// returns the type or why the name cannot be taken
+    pub fn register(&mut self, name: &str, handler: Arc<dyn TransactionHandler>) -> Result<TransactionType, String> {
+        self.handlers.register(name, handler)
+    }
+
+    /// Validate every transaction with custom scripts before risk rules
+    pub(crate) fn set_scripts(&mut self, scripts: Scripts) {
+        self.scripts = Some(scripts);
+    }
+
+    /// Resume reviews from a queue persisted by an earlier run
+    pub(crate) fn set_review_queue(&mut self, review: ReviewQueue) {
+        self.review = review;
+    }
+
+    /// Transactions held for review, decisions are taken into account by input that follows
+    pub(crate) fn review_queue_mut(&mut self) -> &mut ReviewQueue {
+        &mut self.review
+    }
+
+    /// Emit an event of every change of an account to sink in addition to the ones added before
+    pub fn add_event_sink(&mut self, sink: Box<dyn EventSink>) {
+        self.events.add_sink(sink);
+    }
+
+    /// Flush event sinks, fails with the first error of a sink not reported by input yet
+    pub fn flush_events(&mut self) -> Result<(), Error> {
+        self.events.flush().map_err(Error::Events)
+    }
+
+    /// Processing statistics of all inputs so far
+    pub(crate) fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    /// Look up an account by id
+    pub fn account(&self, id: AccountId) -> Option<&Account> {
+        self.account_map.get(&id)
+    }
+
+    /// All accounts in no particular order
+    pub(crate) fn accounts(&self) -> impl Iterator<Item = &Account> {
+        self.account_map.values()
     }
 
     /// Read transactions, apply to accounts, write accounts
-    pub(crate) fn process<R, W>(&mut self, read: R, write: W)
+    pub fn process<R, W>(&mut self, read: R, write: W) -> Result<(), Error>
     where
         R: Read,
         W: Write,
     {
-        self.input(read);
-
-        if let Err(e) = self.output(write) {
-            eprintln!("Failed to serialize accounts: {}", e);
-        }
+        self.input(read)?;
+        self.output(write)
     }
 
     /// Deserialize transactions from reader, ignore record if cannot parse it,
     /// apply transactions to accounts' aggregate values and collect accounts in memory
-    pub(crate) fn input<R>(&mut self, rdr: R)
+    pub fn input<R>(&mut self, rdr: R) -> Result<(), Error>
+    where
+        R: Read,
+    {
+        self.input_dialect(rdr, &Dialect::default())
+    }
+
+    /// Same as `input` for a csv file in a partner dialect
+    pub fn input_dialect<R>(&mut self, rdr: R, dialect: &Dialect) -> Result<(), Error>
     where
         R: Read,
     {
-        let mut reader = ReaderBuilder::new()
-            .trim(Trim::All) // trim leading and trailing whitespace
-            .flexible(true) // allow for missing columns like amount
-            .from_reader(rdr);
-
-        for result in reader.deserialize() {
-            // parse transaction from csv and ignore if error
-            let transaction: Transaction = match result {
-                Ok(transaction) => transaction,
-                Err(error) => {
-                    eprintln!("cannot parse transaction for {error}");
-                    continue;
+        self.input_with(rdr, dialect, |_, rejection| warn!(reason = rejection.kind(), "{rejection}"))?;
+        Ok(())
+    }
+
+    /// Same as `input_dialect` but hands every rejected record with its number to `on_reject` instead of printing it,
+    /// returns the number of records read
+    pub(crate) fn input_with<R, F>(&mut self, rdr: R, dialect: &Dialect, mut on_reject: F) -> Result<usize, Error>
+    where
+        R: Read,
+        F: FnMut(usize, Rejection),
+    {
+        // decompress gzip, zstd or bzip2 detected by magic bytes
+        let rdr = Decoder::new(rdr, Compression::None).map_err(|e| Error::Input(e.into()))?;
+
+        let mut reader = dialect.reader(rdr);
+        let headers = dialect.input_headers(&mut reader).map_err(Error::Input)?;
+
+        let start = Instant::now();
+        let mut started = start;
+        let mut records = 0;
+
+        for result in reader.records() {
+            records += 1;
+
+            let result = result.and_then(|record| dialect.deserialize::<Transaction>(record, &headers));
+
+            // span of one record, transaction fields are known once it is parsed,
+            // the level is that of rejections so they carry the span with the default filter
+            let span = span!(Level::WARN, "transaction", record = records, client = field::Empty, tx = field::Empty, r#type = field::Empty);
+            let _enter = span.enter();
+
+            // parse transaction from csv and apply it to its account
+            let outcome = match result {
+                // custom types only parse in engines with a handler of them
+                Ok(transaction) if !self.handlers.is_known(transaction.transaction_type) => {
+                    Err(Rejection::UnknownType(transaction.transaction_type))
+                }
+                Ok(transaction) => self.apply(transaction, &span, started),
+                // the rest of the input cannot be trusted when reading it fails
+                Err(error) if error.is_io_error() => return Err(Error::Input(error)),
+                Err(error) => Err(Rejection::Parse(error)),
+            };
+
+            // ignore the record if rejected unless asked to fail fast
+            if let Err(rejection) = outcome {
+                match &rejection {
+                    Rejection::Parse(..) | Rejection::UnknownType(..) => self.stats.parse_error(rejection.kind()),
+                    Rejection::Apply(transaction_type, ..)
+                    | Rejection::Risk(transaction_type, ..)
+                    | Rejection::Declined(transaction_type, ..)
+                    | Rejection::Script(transaction_type, ..) => {
+                        self.stats.rejected(*transaction_type, rejection.kind())
+                    }
+                }
+                if let Some(metrics) = &self.metrics {
+                    metrics.rejected(rejection.stage(), rejection.kind(), self.stats.accounts_created, started.elapsed());
                 }
+
+                if self.fail_fast {
+                    self.stats.records += records;
+                    self.stats.elapsed(start.elapsed());
+                    return Err(Error::Rejected { record: records, rejection });
+                }
+                on_reject(records, rejection);
+            }
+
+            // downstream systems must not miss changes, a failing sink stops processing like failing input
+            if let Some(e) = self.events.take_error() {
+                return Err(Error::Events(e));
+            }
+
+            started = Instant::now();
+        }
+
+        self.stats.records += records;
+        self.stats.elapsed(start.elapsed());
+
+        Ok(records)
+    }
+
+    /// Apply a parsed transaction to its account and account for it in statistics and metrics
+    fn apply(&mut self, transaction: Transaction, span: &Span, started: Instant) -> Result<(), Rejection> {
+        span.record("client", transaction.account_id);
+        span.record("tx", transaction.id);
+        span.record("type", field::debug(transaction.transaction_type));
+
+        // book interest of the periods that ended before this transaction
+        if let (Some(period), Some(timestamp)) = (self.config.interest.period_secs, transaction.timestamp) {
+            let boundary = timestamp - timestamp % period;
+            if self.accrued_until.is_none_or(|until| boundary > until) {
+                self.accrue(boundary);
+            }
+        }
+
+        if self.config.is_internal(transaction.account_id) {
+            return Err(Rejection::Apply(transaction.transaction_type, account::Error::InternalAccount(transaction.account_id)));
+        }
+
+        // find account in the map or create it if not found
+        let account = self
+            .account_map
+            .entry(transaction.account_id)
+            .or_insert_with_key(|id| {
+                self.stats.accounts_created += 1;
+                Account::new(*id)
+            });
+
+        let transaction_type = transaction.transaction_type;
+        let (locked, flagged) = (account.locked, account.flagged);
+        let before = account.balance();
+
+        // custom scripts see the transaction and the account balances before it
+        if let Some(scripts) = &mut self.scripts {
+            scripts
+                .validate(&transaction, account)
+                .map_err(|rejected| Rejection::Script(transaction_type, rejected))?;
+        }
+
+        // the most severe risk rule hit decides if the transaction gets to the account
+        let hits = self.risk.evaluate(&self.config.risk, &transaction);
+        for hit in hits.iter() {
+            self.stats.risk_hit(&hit.rule);
+            if hit.action == Action::Allow {
+                warn!(rule = %hit.rule, "risk rule hit");
+            }
+        }
+        if let Some(hit) = hits.into_iter().filter(|hit| hit.action != Action::Allow).max_by_key(|hit| hit.action) {
+            // held transactions wait in the review queue until approved, then apply in their original place
+            let status = match hit.action {
+                Action::Hold => self.review.hold(&transaction, &hit.rule),
+                _ => Status::Pending,
             };
+            match status {
+                Status::Approved => debug!(rule = %hit.rule, "approved in review"),
+                Status::Declined => return Err(Rejection::Declined(transaction_type, transaction.id)),
+                Status::Pending => {
+                    if hit.action == Action::Lock && account.lock(hit.to_string()) {
+                        self.stats.accounts_locked += 1;
+                        self.events.status(EventKind::AccountLocked, account, transaction.id, transaction_type, transaction.timestamp);
+                    }
+                    return Err(Rejection::Risk(transaction_type, hit));
+                }
+            }
+        }
+
+        let client = transaction.account_id;
+        account
+            .apply_transaction(transaction, &self.config, &self.handlers)
+            .map_err(|error| Rejection::Apply(transaction_type, error))?;
+
+        self.risk.record(&self.config.risk, client, transaction_type);
+
+        let Some(entry) = account.history().last().cloned() else {
+            return Ok(());
+        };
+
+        debug!(amount = entry.amount, fee = entry.fee, "transaction applied");
+        self.stats.accepted(&entry);
+        if let Some(metrics) = &self.metrics {
+            metrics.applied(&entry, self.stats.accounts_created, started.elapsed());
+        }
+        self.events.applied(client, &entry, before);
+        if account.locked && !locked {
+            self.stats.accounts_locked += 1;
+            self.events.status(EventKind::AccountLocked, account, entry.id, transaction_type, entry.timestamp);
+        }
+        if account.flagged && !flagged {
+            self.stats.accounts_flagged += 1;
+            warn!(reason = account.reason.as_deref(), "account flagged");
+            self.events.status(EventKind::AccountFlagged, account, entry.id, transaction_type, entry.timestamp);
+        }
+
+        // validated config has the fee account whenever fees are charged
+        if let (Some(fee_account), true) = (self.config.fees.account, entry.fee != 0.0) {
+            let account = self.account_map.entry(fee_account).or_insert_with(|| Account::new(fee_account));
+            let before = account.balance();
+            account.book_fee(&entry);
+            self.events.fee_booked(account, &entry, before);
+        }
+
+        Ok(())
+    }
+
+    /// Book interest of every client account accrued until timestamp as system generated transactions in client order,
+    /// returns the number of transactions booked
+    pub(crate) fn accrue(&mut self, until: Timestamp) -> usize {
+        let mut ids: Vec<AccountId> = self.account_map.keys().copied().filter(|id| !self.config.is_internal(*id)).collect();
+        ids.sort_unstable();
 
-            // find account in the map or create it if not found
-            let account = self
-                .account_map
-                .entry(transaction.account_id)
-                .or_insert_with_key(|id| Account::new(*id));
+        let mut booked = 0;
+        for id in ids {
+            let Some(account) = self.account_map.get_mut(&id) else { continue };
 
-            // apply transaction to the account from csv and ignore if error
-            if let Err(error) = account.apply_transaction(transaction) {
-                eprintln!("cannot apply transaction for {error}");
-                continue;
+            let before = account.balance();
+            if let Some(entry) = account.accrue(self.next_system_tx, until, &self.config) {
+                debug!(client = id, tx = entry.id, amount = entry.amount, "interest booked");
+                self.stats.accepted(entry);
+                if let Some(metrics) = &self.metrics {
+                    metrics.applied(entry, self.stats.accounts_created, Duration::ZERO);
+                }
+                self.events.applied(id, entry, before);
+
+                self.next_system_tx -= 1;
+                booked += 1;
             }
         }
+
+        self.accrued_until = self.accrued_until.max(Some(until));
+        booked
     }
 
     /// Serialize accounts from memory to writer
-    pub(crate) fn output<W>(&self, wtr: W) -> Result<(), Error>
+    pub fn output<W>(&self, wtr: W) -> Result<(), Error>
     where
         W: Write,
     {
-        let mut writer = WriterBuilder::new().from_writer(wtr);
+        self.output_dialect(wtr, &Dialect::default())
+    }
+
+    /// Same as `output` in a partner dialect
+    pub fn output_dialect<W>(&self, wtr: W, dialect: &Dialect) -> Result<(), Error>
+    where
+        W: Write,
+    {
+        // credit columns are only written when there are credit lines to report
+        let credit = !self.config.credit.is_empty();
+        let columns: &[&str] = if credit { &account::CREDIT_COLUMNS } else { &account::COLUMNS };
+
+        let mut writer = dialect.writer(wtr, columns).map_err(Error::Output)?;
 
         for account in self.account_map.values() {
-            writer.serialize(&account)?;
+            let result = if credit {
+                let limit = self.config.credit_limit(account.id());
+                dialect.serialize(&mut writer, columns, &account.with_credit(limit))
+            } else {
+                dialect.serialize(&mut writer, columns, account)
+            };
+            result.map_err(Error::Output)?;
         }
 
-        Ok(())
+        // surface errors that dropping the writer would swallow
+        writer.flush().map_err(|e| Error::Output(e.into()))
     }
 }
 
@@ -86,6 +486,7 @@ mod tests {
     use super::*;
     use std::io;
     use approx::assert_relative_eq;
+    use crate::transaction::TransactionType;
 
     #[test]
     /// smoke test to observe accounts on std out
@@ -100,8 +501,8 @@ resolve,         1,  1,
 chargeback,      1,  1,
 ";
 
-        let mut engine = Engine::new();
-        engine.process(csv.as_bytes(), io::stdout());
+        let mut engine = Engine::new(EngineConfig::default());
+        engine.process(csv.as_bytes(), io::stdout()).unwrap();
     }
 
     #[test]
@@ -117,8 +518,8 @@ resolve,         1,  1,
 chargeback,      1,  1,
 ";
 
-        let mut engine = Engine::new();
-        engine.input(csv.as_bytes());
+        let mut engine = Engine::new(EngineConfig::default());
+        engine.input(csv.as_bytes()).unwrap();
 
         let a1 = engine.account_map.get(&1).unwrap();
         assert_eq!(a1.available.0, 2.0);
@@ -145,8 +546,8 @@ withdrawal, 1,      4,  3.0
 withdrawal, 2,      5,  2.1
 ";
 
-        let mut engine = Engine::new();
-        engine.input(csv.as_bytes());
+        let mut engine = Engine::new(EngineConfig::default());
+        engine.input(csv.as_bytes()).unwrap();
 
         let a1 = engine.account_map.get(&1).unwrap();
         assert_eq!(a1.available.0, 0.0);
@@ -173,8 +574,8 @@ dispute,    1,      1,
 withdrawal, 1,      3,  2.1
 ";
 
-        let mut engine = Engine::new();
-        engine.input(csv.as_bytes());
+        let mut engine = Engine::new(EngineConfig::default());
+        engine.input(csv.as_bytes()).unwrap();
 
         let a1 = engine.account_map.get(&1).unwrap();
         assert_eq!(a1.available.0, 2.0);
@@ -183,6 +584,35 @@ withdrawal, 1,      3,  2.1
         assert_eq!(a1.locked, false);
     }
 
+    #[test]
+    /// configured policies: disputed withdrawal charged back to an account that stays unlocked, rounded amounts
+    fn config() {
+        let csv = "\
+type,       client, tx, amount
+deposit,    1,      1,  2.00004
+withdrawal, 1,      2,  1.0
+dispute,    1,      2,
+chargeback, 1,      2,
+deposit,    1,      3,  1.0
+";
+
+        let config = EngineConfig {
+            disputable: vec![TransactionType::Deposit, TransactionType::Withdrawal],
+            lock_on_chargeback: false,
+            amount_places: Some(4),
+            ..EngineConfig::default()
+        };
+
+        let mut engine = Engine::new(config);
+        engine.input(csv.as_bytes()).unwrap();
+
+        let a1 = engine.account_map.get(&1).unwrap();
+        assert_eq!(a1.available.0, 3.0);
+        assert_eq!(a1.held.0, 0.0);
+        assert_eq!(a1.total.0, 3.0);
+        assert!(!a1.locked);
+    }
+
     #[test]
     /// resolve after dispute brings available balance back and allows withdrawal
     fn resolve() {
@@ -194,8 +624,8 @@ resolve,    1,      1,
 withdrawal, 1,      2,  1.0
 ";
 
-        let mut engine = Engine::new();
-        engine.input(csv.as_bytes());
+        let mut engine = Engine::new(EngineConfig::default());
+        engine.input(csv.as_bytes()).unwrap();
 
         let a1 = engine.account_map.get(&1).unwrap();
         assert_eq!(a1.available.0, 0.0);
@@ -214,8 +644,8 @@ dispute,    1,      1,  1.0
 withdrawal, 1,      2,  1.0
 ";
 
-        let mut engine = Engine::new();
-        engine.input(csv.as_bytes());
+        let mut engine = Engine::new(EngineConfig::default());
+        engine.input(csv.as_bytes()).unwrap();
 
         let a1 = engine.account_map.get(&1).unwrap();
         assert_eq!(a1.available.0, 0.0);
@@ -234,8 +664,8 @@ resolve,    1,      1,
 withdrawal, 1,      2,  1.0
 ";
 
-        let mut engine = Engine::new();
-        engine.input(csv.as_bytes());
+        let mut engine = Engine::new(EngineConfig::default());
+        engine.input(csv.as_bytes()).unwrap();
 
         let a1 = engine.account_map.get(&1).unwrap();
         assert_eq!(a1.available.0, 0.0);
@@ -254,8 +684,8 @@ chargeback,    1,      1,
 withdrawal, 1,      2,  1.0
 ";
 
-        let mut engine = Engine::new();
-        engine.input(csv.as_bytes());
+        let mut engine = Engine::new(EngineConfig::default());
+        engine.input(csv.as_bytes()).unwrap();
 
         let a1 = engine.account_map.get(&1).unwrap();
         assert_eq!(a1.available.0, 0.0);
@@ -274,8 +704,8 @@ withdrawal, 1,      2,  1.0
 dispute,    1,      2,
 ";
 
-        let mut engine = Engine::new();
-        engine.input(csv.as_bytes());
+        let mut engine = Engine::new(EngineConfig::default());
+        engine.input(csv.as_bytes()).unwrap();
 
         let a1 = engine.account_map.get(&1).unwrap();
         assert_eq!(a1.available.0, 0.0);
@@ -284,6 +714,69 @@ dispute,    1,      2,
         assert_eq!(a1.locked, false);
     }
 
+    #[test]
+    /// fail fast stops at the first rejected record leaving the following ones unapplied
+    fn fail_fast() {
+        let csv = "\
+type,       client, tx, amount
+deposit,    1,      1,  1.0
+withdrawal, 1,      2,  2.0
+deposit,    1,      3,  1.0
+";
+
+        let mut engine = Engine::new(EngineConfig::default());
+        engine.set_fail_fast(true);
+
+        match engine.input(csv.as_bytes()) {
+            Err(Error::Rejected { record, rejection }) => {
+                assert_eq!(record, 2);
+                assert_eq!(rejection.kind(), "InsufficientFunds");
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+
+        let a1 = engine.account_map.get(&1).unwrap();
+        assert_eq!(a1.total.0, 1.0);
+    }
+
+    #[test]
+    /// counters by transaction type, volumes and accounts
+    fn stats() {
+        let csv = "\
+type,       client, tx, amount
+deposit,    1,      1,  1.0
+deposit,    1,      2,  2.0
+deposit,    2,      3,  abc
+withdrawal, 1,      4,  5.0
+withdrawal, 1,      5,  0.5
+dispute,    1,      1,
+chargeback, 1,      1,
+deposit,    1,      6,  1.0
+";
+
+        let mut engine = Engine::new(EngineConfig::default());
+        engine.input(csv.as_bytes()).unwrap();
+
+        let stats = engine.stats();
+        assert_eq!(stats.records, 8);
+        assert_eq!(stats.parse_errors.get("ParseFloat"), Some(&1));
+
+        let deposits = &stats.transactions[&TransactionType::Deposit];
+        assert_eq!(deposits.accepted, 2);
+        assert_eq!(deposits.rejected.get("AccountLocked"), Some(&1));
+
+        let withdrawals = &stats.transactions[&TransactionType::Withdrawal];
+        assert_eq!(withdrawals.accepted, 1);
+        assert_eq!(withdrawals.rejected.get("InsufficientFunds"), Some(&1));
+
+        assert_eq!(stats.deposited.0, 3.0);
+        assert_eq!(stats.withdrawn.0, 0.5);
+        assert_eq!(stats.held.0, 1.0);
+        assert_eq!(stats.charged_back.0, 1.0);
+        assert_eq!(stats.accounts_created, 1);
+        assert_eq!(stats.accounts_locked, 1);
+    }
+
     #[test]
     /// chargeback locks account, moves from held back to available, prevents withdrawal
     fn chargeback() {
@@ -296,8 +789,8 @@ chargeback, 1,      1,
 withdrawal, 1,      3,  2.0
 ";
 
-        let mut engine = Engine::new();
-        engine.input(csv.as_bytes());
+        let mut engine = Engine::new(EngineConfig::default());
+        engine.input(csv.as_bytes()).unwrap();
 
         let a1 = engine.account_map.get(&1).unwrap();
         assert_eq!(a1.available.0, 2.0); // funds intact despite an attempt to withdraw by tx 3
@@ -305,4 +798,339 @@ withdrawal, 1,      3,  2.0
         assert_eq!(a1.total.0, 2.0);
         assert_eq!(a1.locked, true);
     }
+
+    #[test]
+    /// locked account still settles its other open disputes unless configured otherwise
+    fn locked_settles_disputes() {
+        let csv = "\
+type,       client, tx, amount
+deposit,    1,      1,  1.0
+deposit,    1,      2,  2.0
+dispute,    1,      1,
+dispute,    1,      2,
+chargeback, 1,      1,
+resolve,    1,      2,
+deposit,    1,      3,  1.0
+";
+
+        let mut engine = Engine::new(EngineConfig::default());
+        engine.input(csv.as_bytes()).unwrap();
+
+        let a1 = engine.account_map.get(&1).unwrap();
+        assert_eq!(a1.available.0, 2.0);
+        assert_eq!(a1.held.0, 0.0);
+        assert_eq!(a1.total.0, 2.0);
+        assert!(a1.locked);
+
+        let config = EngineConfig {
+            locked_accepts: vec![],
+            ..EngineConfig::default()
+        };
+
+        let mut engine = Engine::new(config);
+        engine.input(csv.as_bytes()).unwrap();
+
+        let a1 = engine.account_map.get(&1).unwrap();
+        assert_eq!(a1.available.0, 0.0);
+        assert_eq!(a1.held.0, 2.0);
+        assert_eq!(a1.total.0, 2.0);
+    }
+
+    #[test]
+    /// fees are charged with their transactions and booked to the internal fee account
+    fn fees() {
+        let csv = "\
+type,       client, tx, amount
+deposit,    1,      1,  10.0
+withdrawal, 1,      2,  9.5
+withdrawal, 1,      3,  8.0
+dispute,    1,      1,
+chargeback, 1,      1,
+deposit,    9,      4,  1.0
+";
+
+        let config: EngineConfig = toml::from_str(
+            r#"
+[fees]
+account = 9
+
+[fees.schedules.withdrawal]
+flat = 0.5
+
+[fees.schedules.chargeback]
+flat = 2.0
+"#,
+        )
+        .unwrap();
+
+        let mut engine = Engine::new(config);
+        engine.input(csv.as_bytes()).unwrap();
+
+        // second withdrawal cannot cover its fee, the chargeback settles and charges its fee into a negative balance
+        let a1 = engine.account_map.get(&1).unwrap();
+        assert_eq!(a1.available.0, -12.0);
+        assert_eq!(a1.held.0, 0.0);
+        assert_eq!(a1.total.0, -12.0);
+        assert!(a1.locked);
+
+        let fees = engine.account_map.get(&9).unwrap();
+        assert_eq!(fees.available.0, 2.5);
+        assert_eq!(fees.history().iter().map(|e| e.id).collect::<Vec<_>>(), vec![2, 1]);
+
+        assert_eq!(engine.stats().fees.0, 2.5);
+        assert!(engine.stats().transactions[&TransactionType::Chargeback].rejected.is_empty());
+        assert_eq!(engine.stats().transactions[&TransactionType::Deposit].rejected.get("InternalAccount"), Some(&1));
+    }
+
+    #[test]
+    /// limits reject deposits and withdrawals before they change balances, period sums follow timestamps
+    fn limits() {
+        let csv = "\
+type,       client, tx, amount, timestamp
+deposit,    1,      1,  100.0,  0
+withdrawal, 1,      2,  30.0,   10
+withdrawal, 1,      3,  30.0,   20
+withdrawal, 1,      4,  30.0,   86400
+deposit,    1,      5,  1000.0, 86400
+";
+
+        let config: EngineConfig = toml::from_str(
+            r#"
+[limits]
+deposit = { per_transaction = 500.0 }
+withdrawal = { per_period = 50.0 }
+"#,
+        )
+        .unwrap();
+
+        let mut engine = Engine::new(config);
+        engine.input(csv.as_bytes()).unwrap();
+
+        let a1 = engine.account_map.get(&1).unwrap();
+        assert_eq!(a1.available.0, 40.0);
+        assert_eq!(a1.total.0, 40.0);
+
+        let stats = engine.stats();
+        assert_eq!(stats.transactions[&TransactionType::Withdrawal].rejected.get("LimitExceeded"), Some(&1));
+        assert_eq!(stats.transactions[&TransactionType::Deposit].rejected.get("LimitExceeded"), Some(&1));
+    }
+
+    #[test]
+    /// transactions without timestamp or out of order count into the latest period
+    fn limits_latest_period() {
+        let config = || toml::from_str::<EngineConfig>("limits = { withdrawal = { per_period = 50.0 } }").unwrap();
+
+        // without timestamps the limit caps all input
+        let csv = "\
+type,       client, tx, amount
+deposit,    1,      1,  100.0
+withdrawal, 1,      2,  30.0
+withdrawal, 1,      3,  30.0
+";
+        let mut engine = Engine::new(config());
+        engine.input(csv.as_bytes()).unwrap();
+        assert_eq!(engine.account_map[&1].available.0, 70.0);
+
+        // an older timestamp does not start its period over
+        let csv = "\
+type,       client, tx, amount, timestamp
+deposit,    1,      1,  100.0,  0
+withdrawal, 1,      2,  30.0,   86400
+withdrawal, 1,      3,  10.0,   10
+withdrawal, 1,      4,  30.0,   86410
+";
+        let mut engine = Engine::new(config());
+        engine.input(csv.as_bytes()).unwrap();
+        assert_eq!(engine.account_map[&1].available.0, 60.0);
+    }
+
+    #[test]
+    /// credit line lets available go negative down to its limit and adds credit columns to the output
+    fn credit() {
+        let csv = "\
+type,       client, tx, amount
+deposit,    1,      1,  10.0
+withdrawal, 1,      2,  40.0
+withdrawal, 1,      3,  30.0
+deposit,    2,      4,  5.0
+withdrawal, 2,      5,  6.0
+";
+
+        let config: EngineConfig = toml::from_str("credit = [{ client = 1, limit = 50.0 }]").unwrap();
+
+        let mut engine = Engine::new(config);
+        engine.input(csv.as_bytes()).unwrap();
+
+        let mut out = vec![];
+        engine.output(&mut out).unwrap();
+
+        let mut lines: Vec<_> = String::from_utf8(out).unwrap().lines().map(String::from).collect();
+        lines[1..].sort();
+        assert_eq!(
+            lines,
+            [
+                "client,available,held,total,locked,credit_used,credit_remaining",
+                "1,-30.0,0.0,-30.0,false,30.0,20.0",
+                "2,5.0,0.0,5.0,false,0.0,0.0",
+            ]
+        );
+    }
+
+    #[test]
+    /// interest accrues on the balance held over time and is booked with system transaction ids
+    fn accrue() {
+        let csv = "\
+type,       client, tx, amount, timestamp
+deposit,    1,      1,  1000.0, 0
+withdrawal, 2,      2,  100.0,  0
+withdrawal, 3,      4,  100.0,  0
+deposit,    1,      3,  1000.0, 864000
+";
+
+        let config: EngineConfig = toml::from_str(
+            r#"
+amount_places = 4
+credit = [{ client = 2, limit = 500.0, rate = 36.5 }, { client = 3, limit = 500.0 }]
+
+[interest]
+rate = 3.65
+overdraft_rate = 10.0
+"#,
+        )
+        .unwrap();
+
+        let mut engine = Engine::new(config);
+        engine.input(csv.as_bytes()).unwrap();
+        assert_eq!(engine.accrue(20 * 86400), 2);
+
+        // 10 days on 1000 and 10 days on 2000 at 0.01% a day, 20 days on -100 at 0.1% a day
+        let a1 = engine.account_map.get(&1).unwrap();
+        assert_relative_eq!(a1.available.0, 2003.0);
+        let interest = a1.history().last().unwrap();
+        assert_eq!((interest.id, interest.transaction_type), (SYSTEM_TX_START, TransactionType::Interest));
+
+        let a2 = engine.account_map.get(&2).unwrap();
+        assert_relative_eq!(a2.available.0, -102.0);
+        assert_eq!(a2.history().last().unwrap().id, SYSTEM_TX_START - 1);
+
+        // the interest-free line is not charged the overdraft rate
+        assert_relative_eq!(engine.account_map[&3].available.0, -100.0);
+
+        assert_relative_eq!(engine.stats().interest.0, 1.0);
+    }
+
+    #[test]
+    /// interest is booked whenever input timestamps cross an accrual period
+    fn accrue_periodically() {
+        let csv = "\
+type,       client, tx, amount, timestamp
+deposit,    1,      1,  1000.0, 0
+deposit,    1,      2,  1.0,    86400
+deposit,    1,      3,  1.0,    86401
+deposit,    1,      4,  1.0,    172800
+";
+
+        let config: EngineConfig = toml::from_str("interest = { rate = 36.5, period_secs = 86400 }").unwrap();
+
+        let mut engine = Engine::new(config);
+        engine.input(csv.as_bytes()).unwrap();
+
+        let types: Vec<_> = engine.account_map[&1].history().iter().map(|e| e.transaction_type).collect();
+        assert_eq!(
+            types,
+            [
+                TransactionType::Deposit,
+                TransactionType::Interest,
+                TransactionType::Deposit,
+                TransactionType::Deposit,
+                TransactionType::Interest,
+                TransactionType::Deposit,
+            ]
+        );
+    }
+
+    #[test]
+    /// held and rejected transactions never reach the account, lock action locks it, allowed hits are counted
+    fn risk_rules() {
+        let csv = "\
+type,       client, tx, amount
+deposit,    1,      1,  5000.0
+deposit,    1,      2,  10.0
+deposit,    2,      3,  10.0
+withdrawal, 2,      4,  900.0
+";
+
+        let config: EngineConfig = toml::from_str(
+            r#"
+[[risk]]
+name = "first"
+rule = "first_large"
+above = 1000.0
+action = "hold"
+
+[[risk]]
+name = "small"
+rule = "amount"
+above = 1.0
+action = "allow"
+
+[[risk]]
+name = "withdrawal"
+rule = "amount"
+above = 500.0
+types = ["withdrawal"]
+action = "lock"
+"#,
+        )
+        .unwrap();
+
+        let mut engine = Engine::new(config);
+        engine.input(csv.as_bytes()).unwrap();
+
+        assert_eq!(engine.account_map[&1].total.0, 10.0);
+        assert!(!engine.account_map[&1].locked);
+        assert_eq!(engine.account_map[&2].total.0, 10.0);
+        assert!(engine.account_map[&2].locked);
+
+        let stats = engine.stats();
+        assert_eq!(stats.risk_hits.get("small"), Some(&4));
+        assert_eq!(stats.transactions[&TransactionType::Deposit].rejected.get("RiskRule"), Some(&1));
+        assert_eq!(stats.accounts_locked, 1);
+    }
+
+    #[test]
+    /// repeated disputes lock or flag an account with the reason recorded on it, chargebacks record theirs
+    fn dispute_policy() {
+        let csv = "\
+type,       client, tx, amount
+deposit,    1,      1,  10.0
+deposit,    1,      2,  20.0
+dispute,    1,      1,
+dispute,    1,      2,
+deposit,    1,      3,  5.0
+deposit,    2,      4,  10.0
+dispute,    2,      4,
+chargeback, 2,      4,
+";
+
+        let mut engine = Engine::new(toml::from_str("disputes = { max_open = 1 }").unwrap());
+        engine.input(csv.as_bytes()).unwrap();
+
+        let a1 = &engine.account_map[&1];
+        assert!(a1.locked);
+        assert_eq!(a1.reason.as_deref(), Some("2 open disputes exceed 1"));
+        assert_eq!(a1.total.0, 30.0);
+        assert_eq!(engine.account_map[&2].reason.as_deref(), Some("chargeback of transaction 4"));
+        assert_eq!(engine.stats().accounts_locked, 2);
+
+        let mut engine = Engine::new(toml::from_str(r#"disputes = { max_ratio = 0.5, action = "flag" }"#).unwrap());
+        engine.input(csv.as_bytes()).unwrap();
+
+        let a1 = &engine.account_map[&1];
+        assert!(!a1.locked);
+        assert!(a1.flagged);
+        assert_eq!(a1.reason.as_deref(), Some("2 disputes of 2 deposits exceed ratio 0.5"));
+        assert_eq!(a1.total.0, 35.0);
+        assert_eq!(engine.stats().accounts_flagged, 2);
+    }
 }
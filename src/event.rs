@@ -0,0 +1,269 @@
+use serde::{Deserialize, Serialize};
+use std::io::{self, Write};
+use std::sync::mpsc::Sender;
+
+use crate::account::{Account, Balance, HistoryEntry, MoneyAggregate};
+use crate::transaction::{AccountId, Timestamp, TransactionId, TransactionType};
+
+/// What changed in an account
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EventKind {
+    FundsDeposited,
+    FundsWithdrawn,
+    FundsHeld,
+    FundsReleased,
+    ChargebackApplied,
+    InterestBooked,
+    FundsAdjusted,
+    CustomApplied,
+    /// Fee of a client transaction credited to the internal fee account
+    FeeBooked,
+    AccountLocked,
+    AccountFlagged,
+}
+
+impl EventKind {
+    /// Event of applying a transaction of a type
+    fn applied(transaction_type: TransactionType) -> Self {
+        match transaction_type {
+            TransactionType::Deposit => EventKind::FundsDeposited,
+            TransactionType::Withdrawal => EventKind::FundsWithdrawn,
+            TransactionType::Dispute => EventKind::FundsHeld,
+            TransactionType::Resolve => EventKind::FundsReleased,
+            TransactionType::Chargeback => EventKind::ChargebackApplied,
+            TransactionType::Interest => EventKind::InterestBooked,
+            TransactionType::AdjustCredit | TransactionType::AdjustDebit => EventKind::FundsAdjusted,
+            TransactionType::Custom(..) => EventKind::CustomApplied,
+        }
+    }
+}
+
+/// Change of an account with its balances before and after, caused by transaction `tx`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Event {
+    /// Position in the stream, consecutive from 1
+    pub seq: u64,
+    pub event: EventKind,
+    pub client: AccountId,
+    pub tx: TransactionId,
+    #[serde(rename = "type")]
+    pub transaction_type: TransactionType,
+    /// Amount moved, none for locks and flags
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amount: Option<MoneyAggregate>,
+    pub fee: MoneyAggregate,
+    pub timestamp: Option<Timestamp>,
+    pub before: Balance,
+    pub after: Balance,
+    /// Why the account is locked or flagged
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// Destination of the event stream
+pub trait EventSink: Send {
+    fn emit(&mut self, event: &Event) -> io::Result<()>;
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Events as json lines written to a file or stdout
+pub struct JsonLines<W: Write + Send> {
+    writer: W,
+}
+
+impl<W: Write + Send> JsonLines<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+}
+
+impl<W: Write + Send> EventSink for JsonLines<W> {
+    fn emit(&mut self, event: &Event) -> io::Result<()> {
+        serde_json::to_writer(&mut self.writer, event)?;
+        self.writer.write_all(b"\n")
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Events sent to a channel, a dropped receiver only stops delivery
+impl EventSink for Sender<Event> {
+    fn emit(&mut self, event: &Event) -> io::Result<()> {
+        let _ = self.send(event.clone());
+        Ok(())
+    }
+}
+
+/// Events of applied transactions numbered in order and handed to every sink
+#[derive(Default)]
+pub(crate) struct EventStream {
+    sinks: Vec<Box<dyn EventSink>>,
+    seq: u64,
+    /// First failure of a sink, the stream keeps going to surface it once the record is done
+    error: Option<io::Error>,
+}
+
+impl EventStream {
+    pub(crate) fn add_sink(&mut self, sink: Box<dyn EventSink>) {
+        self.sinks.push(sink);
+    }
+
+    /// Event of a transaction applied to an account with the balances before it
+    pub(crate) fn applied(&mut self, client: AccountId, entry: &HistoryEntry, before: Balance) {
+        self.emit(Event {
+            seq: 0,
+            event: EventKind::applied(entry.transaction_type),
+            client,
+            tx: entry.id,
+            transaction_type: entry.transaction_type,
+            amount: Some(MoneyAggregate(entry.amount)),
+            fee: MoneyAggregate(entry.fee),
+            timestamp: entry.timestamp,
+            before,
+            after: entry.balance,
+            reason: None,
+        });
+    }
+
+    /// Event of the fee charged by a client transaction credited to the internal fee account with its balances before it
+    pub(crate) fn fee_booked(&mut self, account: &Account, charged: &HistoryEntry, before: Balance) {
+        self.emit(Event {
+            seq: 0,
+            event: EventKind::FeeBooked,
+            client: account.id(),
+            tx: charged.id,
+            transaction_type: charged.transaction_type,
+            amount: Some(MoneyAggregate(charged.fee)),
+            fee: MoneyAggregate::default(),
+            timestamp: charged.timestamp,
+            before,
+            after: account.balance(),
+            reason: None,
+        });
+    }
+
+    /// Event of an account locked or flagged by transaction `tx` with the reason recorded on it, balances are unchanged
+    pub(crate) fn status(&mut self, event: EventKind, account: &Account, tx: TransactionId, transaction_type: TransactionType, timestamp: Option<Timestamp>) {
+        self.emit(Event {
+            seq: 0,
+            event,
+            client: account.id(),
+            tx,
+            transaction_type,
+            amount: None,
+            fee: MoneyAggregate::default(),
+            timestamp,
+            before: account.balance(),
+            after: account.balance(),
+            reason: account.reason.clone(),
+        });
+    }
+
+    fn emit(&mut self, mut event: Event) {
+        self.seq += 1;
+        event.seq = self.seq;
+
+        for sink in self.sinks.iter_mut() {
+            if let Err(e) = sink.emit(&event) {
+                self.error.get_or_insert(e);
+            }
+        }
+    }
+
+    /// Take the first failure of a sink since the last call
+    pub(crate) fn take_error(&mut self) -> Option<io::Error> {
+        self.error.take()
+    }
+
+    /// Flush all sinks, returns the first failure of a sink so far
+    pub(crate) fn flush(&mut self) -> io::Result<()> {
+        for sink in self.sinks.iter_mut() {
+            if let Err(e) = sink.flush() {
+                self.error.get_or_insert(e);
+            }
+        }
+        self.take_error().map_or(Ok(()), Err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EngineConfig;
+    use crate::engine::Engine;
+    use std::sync::mpsc;
+
+    #[test]
+    /// every applied transaction emits an event with balances before and after, locks follow their cause
+    fn stream() {
+        let csv = "\
+type,       client, tx, amount
+deposit,    1,      1,  10.0
+withdrawal, 1,      2,  50.0
+withdrawal, 1,      3,  4.0
+dispute,    1,      1,
+chargeback, 1,      1,
+";
+
+        let (sender, receiver) = mpsc::channel();
+        let mut engine = Engine::new(EngineConfig::default());
+        engine.add_event_sink(Box::new(sender));
+        engine.input(csv.as_bytes()).unwrap();
+        engine.flush_events().unwrap();
+
+        let events: Vec<Event> = receiver.try_iter().collect();
+        let kinds: Vec<_> = events.iter().map(|event| (event.seq, event.event, event.tx)).collect();
+        assert_eq!(
+            kinds,
+            [
+                (1, EventKind::FundsDeposited, 1),
+                (2, EventKind::FundsWithdrawn, 3),
+                (3, EventKind::FundsHeld, 1),
+                (4, EventKind::ChargebackApplied, 1),
+                (5, EventKind::AccountLocked, 1),
+            ]
+        );
+
+        let held = &events[2];
+        assert_eq!((held.before.available.0, held.before.held.0), (6.0, 0.0));
+        assert_eq!((held.after.available.0, held.after.held.0), (-4.0, 10.0));
+        assert_eq!(events[4].reason.as_deref(), Some("chargeback of transaction 1"));
+
+        let mut out = vec![];
+        let mut lines = JsonLines::new(&mut out);
+        lines.emit(&events[0]).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"seq\":1,\"event\":\"FundsDeposited\",\"client\":1,\"tx\":1,\"type\":\"deposit\",\"amount\":10.0,\"fee\":0.0,\"timestamp\":null,\
+\"before\":{\"available\":0.0,\"held\":0.0,\"total\":0.0},\"after\":{\"available\":10.0,\"held\":0.0,\"total\":10.0}}\n"
+        );
+    }
+
+    #[test]
+    /// fees credited to the fee account follow the transaction charging them
+    fn fee_booked() {
+        let csv = "\
+type,       client, tx, amount
+deposit,    1,      1,  10.0
+withdrawal, 1,      2,  4.0
+";
+
+        let (sender, receiver) = mpsc::channel();
+        let mut engine = Engine::new(toml::from_str("fees = { account = 9, schedules.withdrawal.flat = 0.5 }").unwrap());
+        engine.add_event_sink(Box::new(sender));
+        engine.input(csv.as_bytes()).unwrap();
+
+        let events: Vec<Event> = receiver.try_iter().collect();
+        let kinds: Vec<_> = events.iter().map(|event| (event.event, event.client, event.tx)).collect();
+        assert_eq!(kinds, [(EventKind::FundsDeposited, 1, 1), (EventKind::FundsWithdrawn, 1, 2), (EventKind::FeeBooked, 9, 2)]);
+
+        let booked = &events[2];
+        assert_eq!((booked.amount, booked.fee), (Some(MoneyAggregate(0.5)), MoneyAggregate(0.0)));
+        assert_eq!((booked.before.available.0, booked.after.available.0), (0.0, 0.5));
+    }
+}
@@ -0,0 +1,176 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::transaction::{AccountId, Money, TransactionType};
+
+/// Flat and percentage part of a fee charged from a transaction amount
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Tier {
+    /// Smallest amount the tier applies to
+    pub(crate) from: Money,
+    pub(crate) flat: Money,
+    pub(crate) percent: f64,
+}
+
+/// Fee of one transaction type: flat plus percentage of the amount, or those of the highest tier the amount reaches,
+/// bounded by min and max
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct FeeSchedule {
+    pub(crate) flat: Money,
+    pub(crate) percent: f64,
+    pub(crate) min: Option<Money>,
+    pub(crate) max: Option<Money>,
+    /// Tiers in ascending order of `from`, replace flat and percent when the amount reaches one
+    pub(crate) tiers: Vec<Tier>,
+}
+
+impl FeeSchedule {
+    /// Fee charged from a transaction of amount
+    pub(crate) fn fee(&self, amount: Money) -> Money {
+        let (flat, percent) = self
+            .tiers
+            .iter()
+            .rev()
+            .find(|tier| amount >= tier.from)
+            .map_or((self.flat, self.percent), |tier| (tier.flat, tier.percent));
+
+        let mut fee = flat + amount * percent / 100.0;
+        if let Some(min) = self.min {
+            fee = fee.max(min);
+        }
+        if let Some(max) = self.max {
+            fee = fee.min(max);
+        }
+        fee
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let amounts = [self.flat, self.percent, self.min.unwrap_or_default(), self.max.unwrap_or_default()];
+        let tiers = self.tiers.iter().flat_map(|t| [t.from, t.flat, t.percent]);
+        if amounts.into_iter().chain(tiers).any(|a| !(a >= 0.0 && a.is_finite())) {
+            return Err("amounts and percentages must not be negative".to_string());
+        }
+
+        if matches!((self.min, self.max), (Some(min), Some(max)) if min > max) {
+            return Err("min is greater than max".to_string());
+        }
+
+        if self.tiers.windows(2).any(|w| w[0].from >= w[1].from) {
+            return Err("tiers are not in ascending order of from".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+/// Fee schedules of one client replacing the default ones of the same type
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ClientFees {
+    pub(crate) client: AccountId,
+    pub(crate) schedules: BTreeMap<TransactionType, FeeSchedule>,
+}
+
+/// Fee schedules by transaction type with per client overrides and the internal account fees are booked to
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Fees {
+    /// Internal account collecting fees, required with any schedule and not accepting input transactions
+    pub(crate) account: Option<AccountId>,
+    /// Schedules by transaction type
+    pub(crate) schedules: BTreeMap<TransactionType, FeeSchedule>,
+    /// Schedules of specific clients
+    pub(crate) clients: Vec<ClientFees>,
+}
+
+impl Fees {
+    /// Fee charged to client for a transaction of amount, zero without a schedule
+    pub(crate) fn fee(&self, client: AccountId, transaction_type: TransactionType, amount: Money) -> Money {
+        self.clients
+            .iter()
+            .find(|c| c.client == client)
+            .and_then(|c| c.schedules.get(&transaction_type))
+            .or_else(|| self.schedules.get(&transaction_type))
+            .map_or(0.0, |schedule| schedule.fee(amount))
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        let schedules = self
+            .schedules
+            .iter()
+            .map(|(t, s)| (format!("{t:?}"), s))
+            .chain(self.clients.iter().flat_map(|c| {
+                c.schedules.iter().map(move |(t, s)| (format!("client {} {t:?}", c.client), s))
+            }))
+            .collect::<Vec<_>>();
+
+        if !schedules.is_empty() && self.account.is_none() {
+            return Err("account is required to book fees to".to_string());
+        }
+
+        if let Some(client) = self.account.filter(|account| self.clients.iter().any(|c| c.client == *account)) {
+            return Err(format!("internal account {client} cannot have client fees"));
+        }
+
+        for (name, schedule) in schedules {
+            schedule.validate().map_err(|e| format!("{name}: {e}"))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// flat, percentage and tiered fees bounded by min and max, client schedules override defaults
+    fn schedules() {
+        let fees: Fees = toml::from_str(
+            r#"
+account = 0
+
+[schedules.withdrawal]
+flat = 0.5
+percent = 1.0
+max = 2.0
+
+[schedules.chargeback]
+min = 1.0
+tiers = [
+    { from = 0.0, percent = 2.0 },
+    { from = 100.0, flat = 5.0 },
+]
+
+[[clients]]
+client = 7
+
+[clients.schedules.withdrawal]
+flat = 0.1
+"#,
+        )
+        .unwrap();
+        assert!(fees.validate().is_ok());
+
+        assert_eq!(fees.fee(1, TransactionType::Withdrawal, 10.0), 0.6);
+        assert_eq!(fees.fee(1, TransactionType::Withdrawal, 1000.0), 2.0);
+        assert_eq!(fees.fee(1, TransactionType::Chargeback, 10.0), 1.0);
+        assert_eq!(fees.fee(1, TransactionType::Chargeback, 80.0), 1.6);
+        assert_eq!(fees.fee(1, TransactionType::Chargeback, 150.0), 5.0);
+        assert_eq!(fees.fee(1, TransactionType::Deposit, 10.0), 0.0);
+        assert_eq!(fees.fee(7, TransactionType::Withdrawal, 10.0), 0.1);
+        assert_eq!(fees.fee(7, TransactionType::Chargeback, 10.0), 1.0);
+
+        let no_account = Fees {
+            account: None,
+            ..fees.clone()
+        };
+        assert!(no_account.validate().is_err());
+
+        let negative: Fees = toml::from_str("account = 0\n[schedules.deposit]\nflat = -1.0").unwrap();
+        assert!(negative.validate().is_err());
+    }
+}
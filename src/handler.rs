@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::account::{Balance, Error};
+use crate::transaction::{AccountId, Money, Transaction, TransactionType};
+
+/// Handlers bundled with the engine, enabled by name with `custom_types` in the config,
+/// manual corrections are the built-in `adjust_credit` and `adjust_debit`
+pub(crate) const BUNDLED: [&str; 1] = ["bonus"];
+
+/// Bundled handler by name
+pub(crate) fn bundled(name: &str) -> Option<Arc<dyn TransactionHandler>> {
+    match name {
+        "bonus" => Some(Arc::new(Bonus)),
+        _ => None,
+    }
+}
+
+/// Validation and balance effects of a custom transaction type
+pub trait TransactionHandler: Send + Sync {
+    /// Validate the transaction against the balances of its account and apply it to them,
+    /// returns the amount moved for history and statistics
+    fn apply(&self, account_id: AccountId, transaction: &Transaction, balance: &mut Balance) -> Result<Money, Error>;
+}
+
+/// Bonus credited to available funds, the amount must be positive
+struct Bonus;
+
+impl TransactionHandler for Bonus {
+    fn apply(&self, _: AccountId, transaction: &Transaction, balance: &mut Balance) -> Result<Money, Error> {
+        let amount = transaction.amount.ok_or(Error::AmountMissingWhenRequired(transaction.id))?;
+        if amount <= 0.0 {
+            return Err(Error::Rejected(transaction.id, "bonus must be positive".to_string()));
+        }
+
+        balance.available.0 += amount;
+        balance.total.0 += amount;
+        Ok(amount)
+    }
+}
+
+/// Handlers of custom transaction types by type
+#[derive(Clone, Default)]
+pub(crate) struct Handlers {
+    handlers: HashMap<TransactionType, Arc<dyn TransactionHandler>>,
+}
+
+impl Handlers {
+    /// Register a handler of a custom type read from input by name, replacing an earlier one of the same name
+    pub(crate) fn register(&mut self, name: &str, handler: Arc<dyn TransactionHandler>) -> Result<TransactionType, String> {
+        let transaction_type = TransactionType::custom(name)?;
+        self.handlers.insert(transaction_type, handler);
+        Ok(transaction_type)
+    }
+
+    /// Built-in types and custom ones with a handler
+    pub(crate) fn is_known(&self, transaction_type: TransactionType) -> bool {
+        !matches!(transaction_type, TransactionType::Custom(..)) || self.handlers.contains_key(&transaction_type)
+    }
+
+    pub(crate) fn get(&self, transaction_type: TransactionType) -> Option<&dyn TransactionHandler> {
+        self.handlers.get(&transaction_type).map(|handler| handler.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EngineConfig;
+    use crate::engine::Engine;
+
+    /// Moves funds between available and held on request, the amount is the share to hold in percent
+    struct Reserve;
+
+    impl TransactionHandler for Reserve {
+        fn apply(&self, _: AccountId, transaction: &Transaction, balance: &mut Balance) -> Result<Money, Error> {
+            let percent = transaction.amount.ok_or(Error::AmountMissingWhenRequired(transaction.id))?;
+            let amount = balance.available.0 * percent / 100.0;
+
+            balance.available.0 -= amount;
+            balance.held.0 += amount;
+            Ok(amount)
+        }
+    }
+
+    #[test]
+    /// bundled and registered handlers apply custom types by name in their engine, unknown names stay invalid
+    /// even when enabled by a config that was not validated
+    fn custom_types() {
+        let csv = "\
+type,       client, tx, amount
+deposit,    1,      1,  100.0
+bonus,      1,      2,  10.0
+bonus,      1,      3,  -10.0
+adjustment, 1,      4,  -10.0
+reserve,    1,      6,  50.0
+cashout,    1,      7,  1.0
+";
+
+        let mut engine = Engine::new(toml::from_str::<EngineConfig>(r#"custom_types = ["bonus", "cashout"]"#).unwrap());
+        let reserve = engine.register("reserve", Arc::new(Reserve)).unwrap();
+        engine.input(csv.as_bytes()).unwrap();
+
+        let a1 = engine.account(1).unwrap();
+        assert_eq!((a1.available.0, a1.held.0, a1.total.0), (55.0, 55.0, 110.0));
+
+        let stats = engine.stats();
+        let bonus = TransactionType::custom("bonus").unwrap();
+        assert_eq!(stats.transactions[&bonus].rejected.get("Rejected"), Some(&1));
+        assert_eq!(stats.transactions[&reserve].accepted, 1);
+        assert_eq!(stats.parse_errors.get("InvalidValue"), Some(&2));
+
+        // types registered with one engine are unknown to another
+        let mut other = Engine::new(EngineConfig::default());
+        other.input(csv.as_bytes()).unwrap();
+        assert_eq!(other.stats().parse_errors.get("InvalidValue"), Some(&5));
+        assert_eq!(other.account(1).unwrap().available.0, 100.0);
+
+        assert!(Handlers::default().register("deposit", Arc::new(Reserve)).is_err());
+        assert!(bundled("cashout").is_none());
+        assert!(bundled("adjustment").is_none());
+    }
+}
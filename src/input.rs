@@ -0,0 +1,109 @@
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::PathBuf;
+
+use crate::compression::{Compression, Decoder};
+
+/// Argument that reads from stdin instead of a file
+pub(crate) const STDIN: &str = "-";
+
+/// Source of transactions csv
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Input {
+    Stdin,
+    File(PathBuf),
+}
+
+impl Input {
+    /// Open the input, a file is decompressed as its extension says unless its magic bytes say otherwise
+    pub(crate) fn open(&self) -> io::Result<Box<dyn Read>> {
+        Ok(match self {
+            Input::Stdin => Box::new(io::stdin()),
+            Input::File(path) => match Compression::from_extension(path) {
+                Compression::None => Box::new(File::open(path)?),
+                compression => Box::new(Decoder::new(File::open(path)?, compression)?),
+            },
+        })
+    }
+}
+
+impl fmt::Display for Input {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Input::Stdin => f.write_str(STDIN),
+            Input::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// Expand arguments into inputs in the order given:
+/// `-` is stdin, a directory is its files sorted by name, a glob is its matches sorted by name,
+/// anything else is a file path
+pub(crate) fn expand<S>(args: &[S]) -> io::Result<Vec<Input>>
+where
+    S: AsRef<str>,
+{
+    let mut inputs = Vec::new();
+
+    for arg in args.iter().map(AsRef::as_ref) {
+        let path = PathBuf::from(arg);
+
+        if arg == STDIN {
+            inputs.push(Input::Stdin);
+        } else if path.is_dir() {
+            let mut files = fs::read_dir(&path)?
+                .map(|entry| entry.map(|e| e.path()))
+                .collect::<io::Result<Vec<_>>>()?;
+            files.retain(|p| p.is_file());
+            files.sort();
+            inputs.extend(files.into_iter().map(Input::File));
+        } else if arg.contains(['*', '?', '[']) {
+            let pattern = glob::glob(arg).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            let mut files = pattern.collect::<Result<Vec<_>, _>>().map_err(io::Error::from)?;
+            if files.is_empty() {
+                return Err(io::Error::new(io::ErrorKind::NotFound, "no files match the pattern"));
+            }
+            files.sort();
+            inputs.extend(files.into_iter().map(Input::File));
+        } else {
+            inputs.push(Input::File(path));
+        }
+    }
+
+    Ok(inputs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tempdir::TempDir;
+
+    #[test]
+    /// directories and globs expand to sorted files, other arguments are kept in order
+    fn expand_args() {
+        let dir = TempDir::new("input");
+        for name in ["b.csv", "a.csv", "c.txt"] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+
+        let d = dir.to_str().unwrap();
+        let inputs = expand(&["-".to_string(), format!("{d}/*.csv"), d.to_string(), "x.csv".to_string()]).unwrap();
+
+        let file = |name: &str| Input::File(dir.join(name));
+        assert_eq!(
+            inputs,
+            vec![
+                Input::Stdin,
+                file("a.csv"),
+                file("b.csv"),
+                file("a.csv"),
+                file("b.csv"),
+                file("c.txt"),
+                Input::File("x.csv".into()),
+            ]
+        );
+
+        assert!(expand(&[format!("{d}/*.json")]).is_err());
+    }
+}
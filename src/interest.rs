@@ -0,0 +1,149 @@
+use serde::{Deserialize, Serialize};
+
+use crate::transaction::{Money, Timestamp};
+
+/// Seconds in a day
+const DAY: f64 = 24.0 * 60.0 * 60.0;
+
+/// Days in a year interest rates are quoted for, elapsed time is counted in actual days
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub(crate) enum DayCount {
+    #[serde(rename = "actual/365")]
+    Actual365,
+    #[serde(rename = "actual/360")]
+    Actual360,
+}
+
+impl DayCount {
+    fn year_days(&self) -> f64 {
+        match self {
+            DayCount::Actual365 => 365.0,
+            DayCount::Actual360 => 360.0,
+        }
+    }
+}
+
+/// How interest grows between bookings, booked interest compounds from one accrual to the next either way
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Compounding {
+    Simple,
+    Daily,
+}
+
+/// Interest rates on available funds and how they accrue
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Interest {
+    /// Annual rate in percent paid on positive available funds
+    pub(crate) rate: f64,
+    /// Annual rate in percent charged on negative available funds of clients without a credit line
+    pub(crate) overdraft_rate: f64,
+    pub(crate) day_count: DayCount,
+    pub(crate) compounding: Compounding,
+    /// Accrue interest of all accounts whenever input timestamps cross a multiple of this many seconds
+    pub(crate) period_secs: Option<Timestamp>,
+}
+
+impl Default for Interest {
+    fn default() -> Self {
+        Self {
+            rate: 0.0,
+            overdraft_rate: 0.0,
+            day_count: DayCount::Actual365,
+            compounding: Compounding::Simple,
+            period_secs: None,
+        }
+    }
+}
+
+impl Interest {
+    /// Interest on balance held for secs at an annual rate in percent
+    pub(crate) fn accrued(&self, balance: Money, rate: f64, secs: Timestamp) -> Money {
+        let daily = rate / 100.0 / self.day_count.year_days();
+        let days = secs as f64 / DAY;
+
+        match self.compounding {
+            Compounding::Simple => balance * daily * days,
+            Compounding::Daily => balance * ((1.0 + daily).powf(days) - 1.0),
+        }
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if [self.rate, self.overdraft_rate].into_iter().any(|rate| rate.is_nan() || rate < 0.0) {
+            return Err("rates must not be negative".to_string());
+        }
+
+        if self.period_secs == Some(0) {
+            return Err("period_secs must be positive".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+/// Interest accrued by an account since it was last booked
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Accrual {
+    /// Point in time interest is accrued until, unknown before the first timestamp of the account
+    since: Option<Timestamp>,
+    pending: Money,
+}
+
+impl Accrual {
+    /// Accrue interest on balance held from the last point in time until timestamp, earlier timestamps accrue nothing
+    pub(crate) fn advance<F>(&mut self, until: Timestamp, balance: Money, interest: F)
+    where
+        F: Fn(Money, Timestamp) -> Money,
+    {
+        match self.since {
+            Some(since) if until > since => {
+                self.pending += interest(balance, until - since);
+                self.since = Some(until);
+            }
+            Some(..) => {}
+            None => self.since = Some(until),
+        }
+    }
+
+    /// Interest accrued so far, starting over from zero
+    pub(crate) fn take(&mut self) -> Money {
+        std::mem::take(&mut self.pending)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    #[test]
+    /// simple and daily compounded interest over day counts, accrual only moves forward in time
+    fn accrual() {
+        let year = 365 * DAY as Timestamp;
+
+        let simple = Interest::default();
+        assert_relative_eq!(simple.accrued(1000.0, 5.0, year), 50.0);
+        assert_relative_eq!(simple.accrued(-1000.0, 10.0, year / 2), -50.0);
+
+        let actual360 = Interest {
+            day_count: DayCount::Actual360,
+            ..Interest::default()
+        };
+        assert_relative_eq!(actual360.accrued(1000.0, 3.6, 10 * DAY as Timestamp), 1.0);
+
+        let daily = Interest {
+            compounding: Compounding::Daily,
+            ..Interest::default()
+        };
+        assert_relative_eq!(daily.accrued(1000.0, 5.0, year), 1000.0 * ((1.0 + 0.05 / 365.0f64).powi(365) - 1.0), epsilon = 1e-9);
+
+        let mut accrual = Accrual::default();
+        let interest = |balance: Money, secs: Timestamp| simple.accrued(balance, 36.5, secs);
+        accrual.advance(100, 1000.0, interest);
+        accrual.advance(100 + DAY as Timestamp, 1000.0, interest);
+        accrual.advance(50, 1000.0, interest);
+        assert_relative_eq!(accrual.take(), 1.0);
+        assert_eq!(accrual.take(), 0.0);
+    }
+}
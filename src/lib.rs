@@ -0,0 +1,91 @@
+//! Payments engine applying client transactions read as csv to accounts.
+//!
+//! The `payments-engine` binary is its command line, library users create an [`Engine`]
+//! and register handlers of custom transaction types with it.
+//!
+//! ```
+//! use std::sync::Arc;
+//! use payments_engine::{AccountError, AccountId, Balance, Engine, EngineConfig, Money, Transaction, TransactionHandler};
+//!
+//! /// Cashback credited to available funds
+//! struct Cashback;
+//!
+//! impl TransactionHandler for Cashback {
+//!     fn apply(&self, _: AccountId, transaction: &Transaction, balance: &mut Balance) -> Result<Money, AccountError> {
+//!         let amount = transaction.amount().ok_or(AccountError::AmountMissingWhenRequired(transaction.id()))?;
+//!         balance.available.0 += amount;
+//!         balance.total.0 += amount;
+//!         Ok(amount)
+//!     }
+//! }
+//!
+//! let mut engine = Engine::new(EngineConfig::default());
+//! let cashback = engine.register("cashback", Arc::new(Cashback)).unwrap();
+//! assert_eq!(cashback.name(), "cashback");
+//! assert!(engine.register("deposit", Arc::new(Cashback)).is_err());
+//!
+//! let csv = "type,client,tx,amount\ndeposit,1,1,10.0\ncashback,1,2,0.5\n";
+//! let mut accounts = Vec::new();
+//! engine.process(csv.as_bytes(), &mut accounts).unwrap();
+//! assert_eq!(String::from_utf8(accounts).unwrap(), "client,available,held,total,locked\n1,10.5,0.0,10.5,false\n");
+//! ```
+//!
+//! Changes of accounts are streamed as [`Event`]s to every [`EventSink`] added to the engine,
+//! e.g. [`JsonLines`] or the sending half of a channel.
+//!
+//! ```
+//! use std::sync::mpsc;
+//! use payments_engine::{Engine, EngineConfig, EventKind};
+//!
+//! let (sender, receiver) = mpsc::channel();
+//! let mut engine = Engine::new(EngineConfig::default());
+//! engine.add_event_sink(Box::new(sender));
+//! engine.input("type,client,tx,amount\ndeposit,1,1,10.0\n".as_bytes()).unwrap();
+//! engine.flush_events().unwrap();
+//!
+//! let event = receiver.recv().unwrap();
+//! assert_eq!((event.seq, event.event, event.after.available.0), (1, EventKind::FundsDeposited, 10.0));
+//! ```
+
+// the original modules keep their explicit style
+#[allow(clippy::needless_return, clippy::useless_vec)]
+mod account;
+#[allow(clippy::useless_vec)]
+mod transaction;
+#[allow(clippy::bool_assert_comparison)]
+mod engine;
+mod statement;
+mod reconcile;
+mod validate;
+mod stats;
+mod metrics;
+mod logging;
+mod input;
+mod compression;
+mod atomic;
+mod audit;
+mod dialect;
+mod event;
+mod config;
+mod fee;
+mod handler;
+mod limit;
+mod credit;
+mod dispute;
+mod interest;
+mod risk;
+mod rejects;
+mod script;
+mod review;
+mod webhook;
+#[cfg(test)]
+mod tempdir;
+pub mod cli;
+
+pub use account::{Balance, Error as AccountError, MoneyAggregate};
+pub use config::{EngineConfig, Error as ConfigError};
+pub use dialect::{Dialect, Error as DialectError};
+pub use engine::{Engine, Error, Rejection};
+pub use event::{Event, EventKind, EventSink, JsonLines};
+pub use handler::TransactionHandler;
+pub use transaction::{AccountId, Money, Timestamp, Transaction, TransactionId, TransactionType};
@@ -0,0 +1,190 @@
+use serde::{Deserialize, Serialize};
+
+use crate::transaction::{AccountId, Money, Timestamp, TransactionType};
+
+/// Seconds in a day, the default limit period
+const DAY: Timestamp = 24 * 60 * 60;
+
+/// Limits of one transaction type, omitted ones are unlimited
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Limit {
+    /// Largest amount of a single transaction
+    pub(crate) per_transaction: Option<Money>,
+    /// Largest sum of amounts within one period
+    pub(crate) per_period: Option<Money>,
+}
+
+impl Limit {
+    /// Name of the limit exceeded by amount with the sum used in the period so far
+    fn exceeded(&self, amount: Money, used: Money) -> Option<&'static str> {
+        if self.per_transaction.is_some_and(|limit| amount > limit) {
+            Some("per transaction")
+        } else if self.per_period.is_some_and(|limit| used + amount > limit) {
+            Some("per period")
+        } else {
+            None
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if [self.per_transaction, self.per_period].into_iter().flatten().any(|limit| limit.is_nan() || limit < 0.0) {
+            return Err("limits must not be negative".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Limits of one client replacing the default ones of the same type
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ClientLimits {
+    pub(crate) client: AccountId,
+    pub(crate) deposit: Option<Limit>,
+    pub(crate) withdrawal: Option<Limit>,
+}
+
+/// Deposit and withdrawal limits of the default tier with per client overrides
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Limits {
+    /// Length of a period in seconds, periods start at multiples of it since the unix epoch
+    pub(crate) period_secs: Timestamp,
+    pub(crate) deposit: Limit,
+    pub(crate) withdrawal: Limit,
+    pub(crate) clients: Vec<ClientLimits>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            period_secs: DAY,
+            deposit: Limit::default(),
+            withdrawal: Limit::default(),
+            clients: Vec::new(),
+        }
+    }
+}
+
+impl Limits {
+    /// Limit of a client for deposits or withdrawals
+    fn limit(&self, client: AccountId, transaction_type: TransactionType) -> Option<&Limit> {
+        let overrides = self.clients.iter().find(|c| c.client == client);
+
+        match transaction_type {
+            TransactionType::Deposit => overrides.and_then(|c| c.deposit.as_ref()).or(Some(&self.deposit)),
+            TransactionType::Withdrawal => overrides.and_then(|c| c.withdrawal.as_ref()).or(Some(&self.withdrawal)),
+            _ => None,
+        }
+    }
+
+    /// Period a transaction with timestamp falls into
+    pub(crate) fn period(&self, timestamp: Timestamp) -> u64 {
+        timestamp / self.period_secs
+    }
+
+    /// Check a transaction of client against its limit given the sum used in the period so far,
+    /// returns the name of the exceeded limit
+    pub(crate) fn check(&self, client: AccountId, transaction_type: TransactionType, amount: Money, used: Money) -> Result<(), &'static str> {
+        match self.limit(client, transaction_type).and_then(|limit| limit.exceeded(amount, used)) {
+            Some(exceeded) => Err(exceeded),
+            None => Ok(()),
+        }
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if self.period_secs == 0 {
+            return Err("period_secs must be positive".to_string());
+        }
+
+        let clients = self.clients.iter().flat_map(|c| [&c.deposit, &c.withdrawal]).flatten();
+        for limit in [&self.deposit, &self.withdrawal].into_iter().chain(clients) {
+            limit.validate()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Sums of deposits and withdrawals of an account in its latest period
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Usage {
+    period: u64,
+    deposited: Money,
+    withdrawn: Money,
+}
+
+impl Usage {
+    /// Period of the latest transaction, transactions without timestamp or with an older one fall into it
+    pub(crate) fn period(&self) -> u64 {
+        self.period
+    }
+
+    /// Sum of transaction type in period, nothing is used yet in a new period
+    pub(crate) fn used(&self, period: u64, transaction_type: TransactionType) -> Money {
+        match transaction_type {
+            _ if period != self.period => 0.0,
+            TransactionType::Deposit => self.deposited,
+            TransactionType::Withdrawal => self.withdrawn,
+            _ => 0.0,
+        }
+    }
+
+    /// Account for an applied transaction, starting over in a new period
+    pub(crate) fn add(&mut self, period: u64, transaction_type: TransactionType, amount: Money) {
+        if period != self.period {
+            *self = Usage {
+                period,
+                ..Usage::default()
+            };
+        }
+
+        match transaction_type {
+            TransactionType::Deposit => self.deposited += amount,
+            TransactionType::Withdrawal => self.withdrawn += amount,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// client overrides replace default limits per type, period sums start over in a new period
+    fn limits() {
+        let limits: Limits = toml::from_str(
+            r#"
+[deposit]
+per_transaction = 100.0
+
+[withdrawal]
+per_period = 50.0
+
+[[clients]]
+client = 7
+withdrawal = { per_transaction = 10.0 }
+"#,
+        )
+        .unwrap();
+        assert!(limits.validate().is_ok());
+
+        assert_eq!(limits.check(1, TransactionType::Deposit, 100.0, 1000.0), Ok(()));
+        assert_eq!(limits.check(1, TransactionType::Deposit, 100.5, 0.0), Err("per transaction"));
+        assert_eq!(limits.check(1, TransactionType::Withdrawal, 20.0, 30.0), Ok(()));
+        assert_eq!(limits.check(1, TransactionType::Withdrawal, 20.0, 30.5), Err("per period"));
+        assert_eq!(limits.check(7, TransactionType::Withdrawal, 20.0, 0.0), Err("per transaction"));
+        assert_eq!(limits.check(7, TransactionType::Deposit, 100.5, 0.0), Err("per transaction"));
+        assert_eq!(limits.check(1, TransactionType::Dispute, 1000.0, 1000.0), Ok(()));
+
+        let mut usage = Usage::default();
+        usage.add(limits.period(10), TransactionType::Withdrawal, 40.0);
+        assert_eq!(usage.used(limits.period(DAY - 1), TransactionType::Withdrawal), 40.0);
+        assert_eq!(usage.used(limits.period(DAY), TransactionType::Withdrawal), 0.0);
+
+        usage.add(limits.period(DAY), TransactionType::Deposit, 1.0);
+        assert_eq!(usage.used(limits.period(DAY), TransactionType::Withdrawal), 0.0);
+        assert_eq!(usage.used(limits.period(DAY), TransactionType::Deposit), 1.0);
+    }
+}
@@ -0,0 +1,30 @@
+use clap::ValueEnum;
+use std::io;
+use tracing_subscriber::EnvFilter;
+
+/// Environment variable with the log filter, e.g. `warn` or `payments_engine=debug`
+pub(crate) const LOG_ENV: &str = "PAYMENTS_LOG";
+
+/// Log line formats
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub(crate) enum LogFormat {
+    /// Human readable lines
+    Text,
+    /// One json object per line with the fields of the current transaction span
+    Json,
+}
+
+/// Install the global subscriber writing to stderr, an invalid filter falls back to `warn`
+pub(crate) fn init(filter: &str, format: LogFormat) {
+    let filter = EnvFilter::try_new(filter).unwrap_or_else(|e| {
+        eprintln!("invalid log filter {filter:?} for {e}, falling back to warn");
+        EnvFilter::new("warn")
+    });
+
+    let builder = tracing_subscriber::fmt().with_env_filter(filter).with_writer(io::stderr);
+
+    match format {
+        LogFormat::Text => builder.init(),
+        LogFormat::Json => builder.json().with_current_span(true).with_span_list(false).init(),
+    }
+}
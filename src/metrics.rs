@@ -0,0 +1,203 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tracing::warn;
+
+use crate::account::HistoryEntry;
+use crate::transaction::{Money, TransactionType};
+
+/// Upper bounds of processing latency buckets in seconds
+const LATENCY_BUCKETS: [f64; 10] = [1e-6, 5e-6, 1e-5, 5e-5, 1e-4, 5e-4, 1e-3, 5e-3, 1e-2, 1e-1];
+
+/// Cumulative histogram in the Prometheus sense
+#[derive(Debug, Default)]
+struct Histogram {
+    /// Count of observations in each bucket, not cumulative, the last one is `+Inf`
+    buckets: [u64; LATENCY_BUCKETS.len() + 1],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        let i = LATENCY_BUCKETS.iter().position(|le| value <= *le).unwrap_or(LATENCY_BUCKETS.len());
+        self.buckets[i] += 1;
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    applied: BTreeMap<TransactionType, u64>,
+    /// Rejections by stage `parse` or `apply` and reason
+    rejected: BTreeMap<(&'static str, &'static str), u64>,
+    accounts: usize,
+    open_disputes: i64,
+    held: Money,
+    latency: Histogram,
+}
+
+/// Live engine metrics shared with the http endpoint
+#[derive(Debug, Default)]
+pub(crate) struct Metrics {
+    inner: Mutex<Inner>,
+}
+
+impl Metrics {
+    /// Record a transaction applied to an account in `latency`
+    pub(crate) fn applied(&self, entry: &HistoryEntry, accounts: usize, latency: Duration) {
+        let mut inner = self.inner.lock().unwrap();
+
+        *inner.applied.entry(entry.transaction_type).or_default() += 1;
+        inner.accounts = accounts;
+
+        match entry.transaction_type {
+            TransactionType::Dispute => {
+                inner.open_disputes += 1;
+                inner.held += entry.amount;
+            }
+            TransactionType::Resolve | TransactionType::Chargeback => {
+                inner.open_disputes -= 1;
+                inner.held -= entry.amount;
+            }
+            _ => {}
+        }
+
+        inner.latency.observe(latency.as_secs_f64());
+    }
+
+    /// Record a record rejected at `stage` for `reason`
+    pub(crate) fn rejected(&self, stage: &'static str, reason: &'static str, accounts: usize, latency: Duration) {
+        let mut inner = self.inner.lock().unwrap();
+
+        *inner.rejected.entry((stage, reason)).or_default() += 1;
+        inner.accounts = accounts;
+        inner.latency.observe(latency.as_secs_f64());
+    }
+
+    /// Render metrics in Prometheus text exposition format
+    pub(crate) fn render(&self) -> String {
+        let inner = self.inner.lock().unwrap();
+        let mut out = String::new();
+
+        // writing into a String cannot fail
+        let _ = writeln!(out, "# HELP payments_transactions_applied_total Transactions applied to accounts by type");
+        let _ = writeln!(out, "# TYPE payments_transactions_applied_total counter");
+        for (transaction_type, count) in inner.applied.iter() {
+            let label = transaction_type.name();
+            let _ = writeln!(out, "payments_transactions_applied_total{{type=\"{label}\"}} {count}");
+        }
+
+        let _ = writeln!(out, "# HELP payments_transactions_rejected_total Records rejected by stage and reason");
+        let _ = writeln!(out, "# TYPE payments_transactions_rejected_total counter");
+        for ((stage, reason), count) in inner.rejected.iter() {
+            let _ = writeln!(out, "payments_transactions_rejected_total{{stage=\"{stage}\",reason=\"{reason}\"}} {count}");
+        }
+
+        let _ = writeln!(out, "# HELP payments_accounts Number of client accounts");
+        let _ = writeln!(out, "# TYPE payments_accounts gauge");
+        let _ = writeln!(out, "payments_accounts {}", inner.accounts);
+
+        let _ = writeln!(out, "# HELP payments_open_disputes Disputes neither resolved nor charged back");
+        let _ = writeln!(out, "# TYPE payments_open_disputes gauge");
+        let _ = writeln!(out, "payments_open_disputes {}", inner.open_disputes);
+
+        let _ = writeln!(out, "# HELP payments_held_funds Total funds held for disputes");
+        let _ = writeln!(out, "# TYPE payments_held_funds gauge");
+        let _ = writeln!(out, "payments_held_funds {}", inner.held);
+
+        let _ = writeln!(out, "# HELP payments_processing_seconds Time to parse and apply one record");
+        let _ = writeln!(out, "# TYPE payments_processing_seconds histogram");
+        let mut cumulative = 0;
+        for (le, count) in LATENCY_BUCKETS.iter().zip(inner.latency.buckets.iter()) {
+            cumulative += count;
+            let _ = writeln!(out, "payments_processing_seconds_bucket{{le=\"{le}\"}} {cumulative}");
+        }
+        let _ = writeln!(out, "payments_processing_seconds_bucket{{le=\"+Inf\"}} {}", inner.latency.count);
+        let _ = writeln!(out, "payments_processing_seconds_sum {}", inner.latency.sum);
+        let _ = writeln!(out, "payments_processing_seconds_count {}", inner.latency.count);
+
+        out
+    }
+}
+
+/// Serve metrics on `GET /metrics` from a background thread
+pub(crate) fn serve(listener: TcpListener, metrics: Arc<Metrics>) -> JoinHandle<()> {
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let result = stream.and_then(|stream| respond(stream, &metrics));
+            if let Err(e) = result {
+                warn!("cannot serve metrics for {e}");
+            }
+        }
+    })
+}
+
+fn respond(mut stream: TcpStream, metrics: &Metrics) -> io::Result<()> {
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+
+    let (status, body) = match request_line.split_whitespace().take(2).collect::<Vec<_>>()[..] {
+        ["GET", "/metrics"] => ("200 OK", metrics.render()),
+        _ => ("404 Not Found", String::new()),
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EngineConfig;
+    use crate::engine::Engine;
+    use std::io::Read;
+
+    #[test]
+    /// engine updates metrics which are served over http
+    fn scrape() {
+        let csv = "\
+type,       client, tx, amount
+deposit,    1,      1,  1.0
+deposit,    2,      2,  2.0
+dispute,    1,      1,
+withdrawal, 2,      3,  5.0
+";
+
+        let metrics = Arc::new(Metrics::default());
+
+        let mut engine = Engine::new(EngineConfig::default());
+        engine.set_metrics(metrics.clone());
+        engine.input(csv.as_bytes()).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        serve(listener, metrics);
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(b"GET /metrics HTTP/1.1\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("payments_transactions_applied_total{type=\"deposit\"} 2\n"));
+        assert!(response.contains("payments_transactions_applied_total{type=\"dispute\"} 1\n"));
+        assert!(response.contains(
+            "payments_transactions_rejected_total{stage=\"apply\",reason=\"InsufficientFunds\"} 1\n"
+        ));
+        assert!(response.contains("payments_accounts 2\n"));
+        assert!(response.contains("payments_open_disputes 1\n"));
+        assert!(response.contains("payments_held_funds 1\n"));
+        assert!(response.contains("payments_processing_seconds_bucket{le=\"+Inf\"} 4\n"));
+        assert!(response.contains("payments_processing_seconds_count 4\n"));
+    }
+}
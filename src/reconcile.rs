@@ -0,0 +1,212 @@
+use csv::{ReaderBuilder, Trim, WriterBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+
+use crate::account::Account;
+use crate::transaction::{AccountId, Money};
+
+/// Default tolerance of amount comparisons, half of the last serialized decimal place and then some
+pub(crate) const DEFAULT_TOLERANCE: Money = 0.0001;
+
+/// Expected account balances with the same schema as engine output
+#[derive(Debug, Deserialize)]
+struct ExpectedAccount {
+    client: AccountId,
+    available: Money,
+    held: Money,
+    total: Money,
+    locked: bool,
+}
+
+/// Kind of a difference between expected and processed accounts
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Issue {
+    /// Account is expected but not found in transactions
+    Missing,
+    /// Account is found in transactions but not expected
+    Extra,
+    /// Field value differs beyond tolerance
+    Mismatch,
+}
+
+/// One difference found by reconciliation, field and values are present for mismatches only
+#[derive(Debug, PartialEq, Serialize)]
+pub(crate) struct Discrepancy {
+    client: AccountId,
+    issue: Issue,
+    field: Option<&'static str>,
+    expected: Option<String>,
+    actual: Option<String>,
+}
+
+impl Discrepancy {
+    fn account(client: AccountId, issue: Issue) -> Self {
+        Self {
+            client,
+            issue,
+            field: None,
+            expected: None,
+            actual: None,
+        }
+    }
+
+    fn mismatch(client: AccountId, field: &'static str, expected: String, actual: String) -> Self {
+        Self {
+            client,
+            issue: Issue::Mismatch,
+            field: Some(field),
+            expected: Some(expected),
+            actual: Some(actual),
+        }
+    }
+}
+
+/// Compare processed accounts with the expected accounts csv,
+/// amounts differing by no more than tolerance are considered equal.
+/// Discrepancies are ordered by client id.
+pub(crate) fn reconcile<'a, R, I>(rdr: R, accounts: I, tolerance: Money) -> Result<Vec<Discrepancy>, csv::Error>
+where
+    R: Read,
+    I: IntoIterator<Item = &'a Account>,
+{
+    let mut reader = ReaderBuilder::new().trim(Trim::All).from_reader(rdr);
+
+    let mut expected = BTreeMap::new();
+    for result in reader.deserialize() {
+        let account: ExpectedAccount = result?;
+        expected.insert(account.client, account);
+    }
+
+    let mut actual: BTreeMap<AccountId, &Account> = accounts.into_iter().map(|a| (a.id(), a)).collect();
+
+    let mut discrepancies = Vec::new();
+
+    for (client, e) in expected.iter() {
+        let Some(a) = actual.remove(client) else {
+            discrepancies.push(Discrepancy::account(*client, Issue::Missing));
+            continue;
+        };
+
+        let amounts = [
+            ("available", e.available, a.available.rounded()),
+            ("held", e.held, a.held.rounded()),
+            ("total", e.total, a.total.rounded()),
+        ];
+
+        for (field, expected, actual) in amounts {
+            if (expected - actual).abs() > tolerance {
+                discrepancies.push(Discrepancy::mismatch(*client, field, expected.to_string(), actual.to_string()));
+            }
+        }
+
+        if e.locked != a.locked {
+            discrepancies.push(Discrepancy::mismatch(*client, "locked", e.locked.to_string(), a.locked.to_string()));
+        }
+    }
+
+    discrepancies.extend(actual.into_keys().map(|client| Discrepancy::account(client, Issue::Extra)));
+    discrepancies.sort_by_key(|d| d.client);
+
+    Ok(discrepancies)
+}
+
+/// Serialize discrepancies into writer as csv
+pub(crate) fn write_report<W>(wtr: W, discrepancies: &[Discrepancy]) -> Result<(), csv::Error>
+where
+    W: Write,
+{
+    let mut writer = WriterBuilder::new().from_writer(wtr);
+
+    for discrepancy in discrepancies {
+        writer.serialize(discrepancy)?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EngineConfig;
+    use crate::engine::Engine;
+
+    const TRANSACTIONS: &str = "\
+type,       client, tx, amount
+deposit,         1,  1,    2.0
+deposit,         2,  2,    1.00004
+deposit,         3,  3,    1.0
+dispute,         3,  3,
+chargeback,      3,  3,
+deposit,         4,  4,    1.0
+";
+
+    fn discrepancies(expected: &str, tolerance: Money) -> Vec<Discrepancy> {
+        let mut engine = Engine::new(EngineConfig::default());
+        engine.input(TRANSACTIONS.as_bytes()).unwrap();
+        reconcile(expected.as_bytes(), engine.accounts(), tolerance).unwrap()
+    }
+
+    #[test]
+    /// balances equal within tolerance reconcile cleanly
+    fn matching() {
+        let expected = "\
+client,available,held,total,locked
+1,2.0,0.0,2.0,false
+2,1.0,0.0,1.0,false
+3,0.0,0.0,0.0,true
+4,1.0,0.0,1.0,false
+";
+        assert!(discrepancies(expected, DEFAULT_TOLERANCE).is_empty());
+    }
+
+    #[test]
+    /// missing, extra and mismatched accounts are reported in client order
+    fn mismatching() {
+        let expected = "\
+client,available,held,total,locked
+1,2.5,0.0,2.5,false
+3,0.0,0.0,0.0,false
+4,1.0,0.0,1.0,false
+5,1.0,0.0,1.0,false
+";
+        let d = discrepancies(expected, DEFAULT_TOLERANCE);
+
+        assert_eq!(
+            d,
+            vec![
+                Discrepancy::mismatch(1, "available", "2.5".into(), "2".into()),
+                Discrepancy::mismatch(1, "total", "2.5".into(), "2".into()),
+                Discrepancy::account(2, Issue::Extra),
+                Discrepancy::mismatch(3, "locked", "false".into(), "true".into()),
+                Discrepancy::account(5, Issue::Missing),
+            ]
+        );
+
+        let mut out = vec![];
+        write_report(&mut out, &d[..1]).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\
+client,issue,field,expected,actual
+1,mismatch,available,2.5,2
+"
+        );
+    }
+
+    #[test]
+    /// a generous tolerance hides small differences
+    fn tolerance() {
+        let expected = "\
+client,available,held,total,locked
+1,2.1,0.0,2.1,false
+2,1.0,0.0,1.0,false
+3,0.0,0.0,0.0,true
+4,1.0,0.0,1.0,false
+";
+        assert_eq!(discrepancies(expected, DEFAULT_TOLERANCE).len(), 2);
+        assert!(discrepancies(expected, 0.5).is_empty());
+    }
+}
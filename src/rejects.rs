@@ -0,0 +1,89 @@
+use csv::{Writer, WriterBuilder};
+use serde::Serialize;
+use std::io::Write;
+
+use crate::engine::Rejection;
+
+/// Row of the rejects report
+#[derive(Serialize)]
+struct Row<'a> {
+    input: &'a str,
+    record: usize,
+    stage: &'static str,
+    reason: &'static str,
+    message: String,
+}
+
+/// Csv report of rejected records with the stage and reason they are rejected at,
+/// risk rules stopping a transaction are named in the message
+pub(crate) struct RejectsReport<W: Write> {
+    writer: Writer<W>,
+}
+
+impl<W: Write> RejectsReport<W> {
+    pub(crate) fn new(wtr: W) -> Self {
+        Self {
+            writer: WriterBuilder::new().from_writer(wtr),
+        }
+    }
+
+    pub(crate) fn write(&mut self, input: &str, record: usize, rejection: &Rejection) -> csv::Result<()> {
+        self.writer.serialize(Row {
+            input,
+            record,
+            stage: rejection.stage(),
+            reason: rejection.kind(),
+            message: rejection.to_string(),
+        })
+    }
+
+    /// Flush the report and return the inner writer
+    pub(crate) fn finish(self) -> csv::Result<W> {
+        self.writer.into_inner().map_err(|e| e.into_error().into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EngineConfig;
+    use crate::dialect::Dialect;
+    use crate::engine::Engine;
+
+    #[test]
+    /// parse, apply and risk rejections are reported with their record numbers
+    fn report() {
+        let csv = "\
+type,       client, tx, amount
+deposit,    1,      1,  abc
+withdrawal, 1,      2,  1.0
+deposit,    1,      3,  5000.0
+";
+        let config: EngineConfig = toml::from_str(
+            r#"
+[[risk]]
+name = "large"
+rule = "amount"
+above = 1000.0
+action = "reject"
+"#,
+        )
+        .unwrap();
+
+        let mut report = RejectsReport::new(vec![]);
+        let mut engine = Engine::new(config);
+        engine
+            .input_with(csv.as_bytes(), &Dialect::default(), |record, rejection| {
+                report.write("in.csv", record, &rejection).unwrap()
+            })
+            .unwrap();
+
+        let out = String::from_utf8(report.finish().unwrap()).unwrap();
+        let lines: Vec<_> = out.lines().collect();
+
+        assert_eq!(lines[0], "input,record,stage,reason,message");
+        assert!(lines[1].starts_with("in.csv,1,parse,ParseFloat,"));
+        assert!(lines[2].starts_with("in.csv,2,apply,InsufficientFunds,"));
+        assert_eq!(lines[3], "in.csv,3,risk,RiskRule,transaction is stopped by risk rule `large` (reject)");
+    }
+}
@@ -0,0 +1,218 @@
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use crate::atomic::AtomicFile;
+use crate::transaction::{AccountId, Money, Timestamp, Transaction, TransactionId, TransactionType};
+
+/// Review state of a held transaction
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Status {
+    Pending,
+    /// Applied whenever the transaction comes up in input
+    Approved,
+    /// Discarded whenever the transaction comes up in input
+    Declined,
+}
+
+/// Transaction held by a risk rule with its review state
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Held {
+    #[serde(flatten)]
+    pub(crate) transaction: Transaction,
+    /// Risk rule that held the transaction
+    pub(crate) rule: String,
+    pub(crate) status: Status,
+}
+
+/// Row of the review list
+#[derive(Serialize)]
+struct Row<'a> {
+    tx: TransactionId,
+    client: AccountId,
+    #[serde(rename = "type")]
+    transaction_type: TransactionType,
+    amount: Option<Money>,
+    timestamp: Option<Timestamp>,
+    rule: &'a str,
+    status: Status,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum Error {
+    #[error("cannot read review queue: {0}")]
+    Read(#[from] io::Error),
+
+    #[error("cannot parse review queue: {0}")]
+    Parse(#[from] serde_json::Error),
+
+    #[error("transaction {0} is not pending review")]
+    NotPending(TransactionId),
+}
+
+/// Transactions held for manual review in the order they were first held,
+/// decisions stay in the queue so processing the same input again applies or discards them in place
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct ReviewQueue {
+    held: Vec<Held>,
+}
+
+impl ReviewQueue {
+    /// Load the queue from a json file, a missing file is an empty queue
+    pub(crate) fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        match File::open(path) {
+            Ok(file) => Ok(serde_json::from_reader(BufReader::new(file))?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Replace the json file with the queue atomically
+    pub(crate) fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut wtr = BufWriter::new(AtomicFile::create(path)?);
+        serde_json::to_writer_pretty(&mut wtr, self)?;
+        writeln!(wtr)?;
+        wtr.into_inner().map_err(|e| e.into_error())?.commit()
+    }
+
+    #[cfg(test)]
+    pub(crate) fn held(&self) -> &[Held] {
+        &self.held
+    }
+
+    /// Write held transactions in queue order as csv
+    pub(crate) fn write_list<W: Write>(&self, wtr: W) -> csv::Result<()> {
+        let mut wtr = csv::Writer::from_writer(wtr);
+        for held in &self.held {
+            let t = &held.transaction;
+            wtr.serialize(Row {
+                tx: t.id,
+                client: t.account_id,
+                transaction_type: t.transaction_type,
+                amount: t.amount,
+                timestamp: t.timestamp,
+                rule: &held.rule,
+                status: held.status,
+            })?;
+        }
+        wtr.flush()?;
+        Ok(())
+    }
+
+    /// Review state of a transaction held by rule, queued as pending the first time it is held,
+    /// a decision only applies to the very transaction reviewed, not to another one reusing its id
+    pub(crate) fn hold(&mut self, transaction: &Transaction, rule: &str) -> Status {
+        match self.held.iter().find(|held| held.transaction == *transaction) {
+            Some(held) => held.status,
+            None => {
+                self.held.push(Held {
+                    transaction: transaction.clone(),
+                    rule: rule.to_string(),
+                    status: Status::Pending,
+                });
+                Status::Pending
+            }
+        }
+    }
+
+    /// Approve or decline pending transactions with id, returns how many are decided
+    pub(crate) fn decide(&mut self, tx: TransactionId, status: Status) -> Result<usize, Error> {
+        let mut decided = 0;
+        for held in self
+            .held
+            .iter_mut()
+            .filter(|h| h.transaction.id == tx && h.status == Status::Pending)
+        {
+            held.status = status;
+            decided += 1;
+        }
+
+        match decided {
+            0 => Err(Error::NotPending(tx)),
+            _ => Ok(decided),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::Engine;
+
+    const CONFIG: &str = r#"
+[[risk]]
+name = "large"
+rule = "amount"
+above = 100.0
+action = "hold"
+"#;
+
+    const CSV: &str = "\
+type,       client, tx, amount
+deposit,    1,      1,  500.0
+deposit,    1,      2,  50.0
+withdrawal, 1,      3,  500.0
+";
+
+    /// Process the input with a queue persisted as json between runs
+    fn run(queue: ReviewQueue) -> Engine {
+        run_csv(queue, CSV)
+    }
+
+    fn run_csv(queue: ReviewQueue, csv: &str) -> Engine {
+        let mut engine = Engine::new(toml::from_str(CONFIG).unwrap());
+        engine.set_review_queue(serde_json::from_str(&serde_json::to_string(&queue).unwrap()).unwrap());
+        engine.input(csv.as_bytes()).unwrap();
+        engine
+    }
+
+    #[test]
+    /// held transactions are applied in their original place once approved and discarded once declined
+    fn approve_and_decline() {
+        let mut engine = run(ReviewQueue::default());
+        assert_eq!(engine.account(1).unwrap().total.0, 50.0);
+
+        let mut queue = std::mem::take(engine.review_queue_mut());
+        assert_eq!(
+            queue.held().iter().map(|h| (h.transaction.id, h.status)).collect::<Vec<_>>(),
+            [(1, Status::Pending), (3, Status::Pending)]
+        );
+        assert_eq!(queue.decide(1, Status::Approved).unwrap(), 1);
+        assert!(matches!(queue.decide(1, Status::Declined), Err(Error::NotPending(1))));
+
+        // the approved deposit comes before the withdrawal which stays held
+        let mut engine = run(queue);
+        assert_eq!(engine.account(1).unwrap().total.0, 550.0);
+        assert_eq!(engine.review_queue_mut().held().len(), 2);
+
+        let mut approved = engine.review_queue_mut().clone();
+        approved.decide(3, Status::Approved).unwrap();
+        assert_eq!(run(approved).account(1).unwrap().total.0, 50.0);
+
+        let mut declined = engine.review_queue_mut().clone();
+        declined.decide(3, Status::Declined).unwrap();
+        let engine = run(declined);
+        assert_eq!(engine.account(1).unwrap().total.0, 550.0);
+        assert_eq!(
+            engine.stats().transactions[&TransactionType::Withdrawal].rejected.get("Declined"),
+            Some(&1)
+        );
+    }
+
+    #[test]
+    /// a transaction reusing the id of an approved one with another amount is held again
+    fn reused_id() {
+        let mut queue = std::mem::take(run(ReviewQueue::default()).review_queue_mut());
+        queue.decide(1, Status::Approved).unwrap();
+
+        let csv = CSV.replace("1,  500.0", "1,  900.0");
+        let mut engine = run_csv(queue, &csv);
+        assert_eq!(engine.account(1).unwrap().total.0, 50.0);
+        assert_eq!(
+            engine.review_queue_mut().held().iter().map(|h| (h.transaction.amount, h.status)).collect::<Vec<_>>(),
+            [(Some(500.0), Status::Approved), (Some(500.0), Status::Pending), (Some(900.0), Status::Pending)]
+        );
+    }
+}
@@ -0,0 +1,235 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+use crate::transaction::{AccountId, Money, Transaction, TransactionType};
+
+/// What happens to a transaction a rule flags, in the order of severity
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    /// Apply the transaction and only report the hit
+    Allow,
+    /// Keep the transaction away from the account until it is reviewed
+    Hold,
+    /// Drop the transaction
+    Reject,
+    /// Drop the transaction and lock the account
+    Lock,
+}
+
+fn default_amount_types() -> Vec<TransactionType> {
+    vec![TransactionType::Deposit, TransactionType::Withdrawal]
+}
+
+/// Condition of a rule on a transaction and the activity of its client before it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "rule", rename_all = "snake_case")]
+pub(crate) enum Condition {
+    /// Deposit makes at least `count` deposits among the last `window` transactions of the client
+    Velocity { count: usize, window: usize },
+    /// Amount of a transaction of one of the types is above a threshold
+    Amount {
+        above: Money,
+        #[serde(default = "default_amount_types")]
+        types: Vec<TransactionType>,
+    },
+    /// Dispute makes the ratio of disputes to deposits of a client with at least `min_deposits` deposits above a threshold
+    DisputeRatio {
+        above: f64,
+        #[serde(default)]
+        min_deposits: usize,
+    },
+    /// First transaction of a client has an amount above a threshold
+    FirstLarge { above: Money },
+}
+
+/// Named risk rule evaluated on every transaction before it is applied
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Rule {
+    pub(crate) name: String,
+    #[serde(flatten)]
+    pub(crate) condition: Condition,
+    pub(crate) action: Action,
+}
+
+impl Rule {
+    fn validate(&self) -> Result<(), String> {
+        match &self.condition {
+            Condition::Velocity { count, window } if *count == 0 || count > window => {
+                Err(format!("{}: count must be positive and not above window", self.name))
+            }
+            Condition::Amount { above, .. } | Condition::DisputeRatio { above, .. } | Condition::FirstLarge { above }
+                if above.is_nan() || *above < 0.0 =>
+            {
+                Err(format!("{}: threshold must not be negative", self.name))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Rules are valid with unique names and sane thresholds
+pub(crate) fn validate(rules: &[Rule]) -> Result<(), String> {
+    for (i, rule) in rules.iter().enumerate() {
+        if rules[..i].iter().any(|r| r.name == rule.name) {
+            return Err(format!("{}: rule name is not unique", rule.name));
+        }
+        rule.validate()?;
+    }
+    Ok(())
+}
+
+/// Rule flagging a transaction
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hit {
+    pub(crate) rule: String,
+    pub(crate) action: Action,
+}
+
+impl fmt::Display for Hit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let action = match self.action {
+            Action::Allow => "allow",
+            Action::Hold => "hold for review",
+            Action::Reject => "reject",
+            Action::Lock => "lock account",
+        };
+        write!(f, "risk rule `{}` ({action})", self.rule)
+    }
+}
+
+/// Activity of a client the rules look at
+#[derive(Debug, Default)]
+struct Activity {
+    /// Types of the latest applied transactions, as many as the widest velocity window
+    recent: VecDeque<TransactionType>,
+    deposits: usize,
+    disputes: usize,
+}
+
+/// Evaluates rules against client activity collected from applied transactions
+#[derive(Debug, Default)]
+pub(crate) struct Risk {
+    activity: HashMap<AccountId, Activity>,
+}
+
+impl Risk {
+    /// Rules a transaction hits given the activity of its client so far
+    pub(crate) fn evaluate(&self, rules: &[Rule], transaction: &Transaction) -> Vec<Hit> {
+        let activity = self.activity.get(&transaction.account_id);
+        let transaction_type = transaction.transaction_type;
+        let amount = transaction.amount.unwrap_or_default();
+
+        let hit = |condition: &Condition| match condition {
+            Condition::Velocity { count, window } => {
+                let deposits = activity.map_or(0, |a| {
+                    a.recent.iter().rev().take(window.saturating_sub(1)).filter(|t| **t == TransactionType::Deposit).count()
+                });
+                transaction_type == TransactionType::Deposit && deposits + 1 >= *count
+            }
+            Condition::Amount { above, types } => types.contains(&transaction_type) && amount > *above,
+            Condition::DisputeRatio { above, min_deposits } => {
+                let (deposits, disputes) = activity.map_or((0, 0), |a| (a.deposits, a.disputes));
+                transaction_type == TransactionType::Dispute
+                    && deposits > 0
+                    && deposits >= *min_deposits
+                    && (disputes + 1) as f64 / deposits as f64 > *above
+            }
+            Condition::FirstLarge { above } => activity.is_none() && amount > *above,
+        };
+
+        rules
+            .iter()
+            .filter(|rule| hit(&rule.condition))
+            .map(|rule| Hit {
+                rule: rule.name.clone(),
+                action: rule.action,
+            })
+            .collect()
+    }
+
+    /// Account for a transaction applied to its client
+    pub(crate) fn record(&mut self, rules: &[Rule], client: AccountId, transaction_type: TransactionType) {
+        let window = rules
+            .iter()
+            .filter_map(|rule| match rule.condition {
+                Condition::Velocity { window, .. } => Some(window),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0);
+
+        let activity = self.activity.entry(client).or_default();
+
+        activity.recent.push_back(transaction_type);
+        while activity.recent.len() > window {
+            activity.recent.pop_front();
+        }
+
+        match transaction_type {
+            TransactionType::Deposit => activity.deposits += 1,
+            TransactionType::Dispute => activity.disputes += 1,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EngineConfig;
+
+    #[test]
+    /// every kind of rule is hit by the activity it describes
+    fn rules() {
+        let rules = toml::from_str::<EngineConfig>(
+            r#"
+[[risk]]
+name = "burst"
+rule = "velocity"
+count = 3
+window = 4
+action = "hold"
+
+[[risk]]
+name = "large"
+rule = "amount"
+above = 500.0
+types = ["withdrawal"]
+action = "reject"
+
+[[risk]]
+name = "disputes"
+rule = "dispute_ratio"
+above = 0.5
+min_deposits = 2
+action = "lock"
+
+[[risk]]
+name = "first"
+rule = "first_large"
+above = 100.0
+action = "allow"
+"#,
+        )
+        .unwrap()
+        .risk;
+        assert!(validate(&rules).is_ok());
+
+        let mut risk = Risk::default();
+        let names = |risk: &Risk, t: Transaction| risk.evaluate(&rules, &t).into_iter().map(|h| h.rule).collect::<Vec<_>>();
+
+        assert_eq!(names(&risk, Transaction::new(TransactionType::Deposit, 1, 1, Some(200.0), false)), ["first"]);
+        risk.record(&rules, 1, TransactionType::Deposit);
+        risk.record(&rules, 1, TransactionType::Deposit);
+        assert_eq!(names(&risk, Transaction::new(TransactionType::Deposit, 1, 3, Some(200.0), false)), ["burst"]);
+        assert_eq!(names(&risk, Transaction::new(TransactionType::Withdrawal, 1, 4, Some(600.0), false)), ["large"]);
+        assert!(names(&risk, Transaction::new(TransactionType::Dispute, 1, 1, None, false)).is_empty());
+        risk.record(&rules, 1, TransactionType::Dispute);
+        assert_eq!(names(&risk, Transaction::new(TransactionType::Dispute, 1, 2, None, false)), ["disputes"]);
+
+        let duplicate = [rules[0].clone(), rules[0].clone()];
+        assert!(validate(&duplicate).is_err());
+    }
+}
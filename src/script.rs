@@ -0,0 +1,241 @@
+use rhai::{Dynamic, Map, Scope, AST};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{error, info, warn};
+
+use crate::account::Account;
+use crate::transaction::Transaction;
+
+/// Function every script defines to validate a transaction against the account it is applied to
+const ENTRY: &str = "validate";
+
+/// How often script files are checked for changes when hot reloading
+const RELOAD_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Operations a script may run per transaction before it is stopped, guards against endless loops
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum Error {
+    #[error("cannot read script {0}: {1}")]
+    Read(String, io::Error),
+
+    #[error("cannot compile script {0}: {1}")]
+    Compile(String, rhai::ParseError),
+
+    #[error("script {0} does not define `{ENTRY}(tx, account)`")]
+    Entry(String),
+}
+
+/// Script rejecting a transaction and its reason
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rejected {
+    pub(crate) script: String,
+    pub(crate) reason: String,
+}
+
+impl fmt::Display for Rejected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "script `{}`: {}", self.script, self.reason)
+    }
+}
+
+/// Compiled script and the modification time of its file
+struct Script {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    ast: AST,
+}
+
+impl Script {
+    fn name(&self) -> String {
+        self.path.display().to_string()
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Custom validation scripts in rhai run in order on every transaction before risk rules,
+/// `validate(tx, account)` returns nothing or `true` to accept, a reason string or `false` to reject
+pub(crate) struct Scripts {
+    engine: rhai::Engine,
+    scripts: Vec<Script>,
+    /// Recompile scripts whose files changed, checked at most every reload interval
+    hot_reload: bool,
+    checked: Instant,
+}
+
+impl Scripts {
+    /// Read and compile script files, all of them must compile and define the entry function
+    pub(crate) fn load<P: AsRef<Path>>(paths: &[P]) -> Result<Self, Error> {
+        let mut engine = rhai::Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+
+        let mut scripts = Self {
+            engine,
+            scripts: Vec::new(),
+            hot_reload: false,
+            checked: Instant::now(),
+        };
+
+        for path in paths {
+            let path = path.as_ref().to_path_buf();
+            let script = scripts.compile(path)?;
+            scripts.scripts.push(script);
+        }
+
+        Ok(scripts)
+    }
+
+    /// Pick up changes of script files while running, a script that fails to compile keeps its last version
+    pub(crate) fn set_hot_reload(&mut self, hot_reload: bool) {
+        self.hot_reload = hot_reload;
+    }
+
+    fn compile(&self, path: PathBuf) -> Result<Script, Error> {
+        let name = path.display().to_string();
+        let modified = modified(&path);
+
+        let source = fs::read_to_string(&path).map_err(|e| Error::Read(name.clone(), e))?;
+        let ast = self.engine.compile(source).map_err(|e| Error::Compile(name.clone(), e))?;
+
+        if !ast.iter_functions().any(|f| f.name == ENTRY && f.params.len() == 2) {
+            return Err(Error::Entry(name));
+        }
+
+        Ok(Script { path, modified, ast })
+    }
+
+    fn reload(&mut self) {
+        if !self.hot_reload || self.checked.elapsed() < RELOAD_INTERVAL {
+            return;
+        }
+        self.checked = Instant::now();
+
+        for i in 0..self.scripts.len() {
+            let path = &self.scripts[i].path;
+            if modified(path) == self.scripts[i].modified {
+                continue;
+            }
+
+            match self.compile(path.clone()) {
+                Ok(script) => {
+                    info!(script = script.name(), "script reloaded");
+                    self.scripts[i] = script;
+                }
+                Err(e) => {
+                    error!("{e}, keeping the last version");
+                    // do not retry until the file changes again
+                    self.scripts[i].modified = modified(&self.scripts[i].path);
+                }
+            }
+        }
+    }
+
+    /// Run scripts on a transaction about to be applied to account, the first rejection wins
+    pub(crate) fn validate(&mut self, transaction: &Transaction, account: &Account) -> Result<(), Rejected> {
+        self.reload();
+
+        if self.scripts.is_empty() {
+            return Ok(());
+        }
+
+        let mut tx = Map::new();
+        tx.insert("tx".into(), Dynamic::from_int(transaction.id.into()));
+        tx.insert("client".into(), Dynamic::from_int(transaction.account_id.into()));
+        tx.insert("type".into(), transaction.transaction_type.name().into());
+        tx.insert("amount".into(), transaction.amount.map_or(Dynamic::UNIT, Dynamic::from_float));
+        tx.insert("timestamp".into(), transaction.timestamp.map_or(Dynamic::UNIT, |t| Dynamic::from_int(t as i64)));
+
+        let mut balances = Map::new();
+        balances.insert("available".into(), Dynamic::from_float(account.available.0));
+        balances.insert("held".into(), Dynamic::from_float(account.held.0));
+        balances.insert("total".into(), Dynamic::from_float(account.total.0));
+        balances.insert("locked".into(), account.locked.into());
+
+        for script in self.scripts.iter() {
+            let result = self.engine.call_fn::<Dynamic>(&mut Scope::new(), &script.ast, ENTRY, (tx.clone(), balances.clone()));
+
+            let reason = match result {
+                Ok(verdict) if verdict.is_unit() => continue,
+                Ok(verdict) => match verdict.as_bool() {
+                    Ok(true) => continue,
+                    Ok(false) => "rejected".to_string(),
+                    Err(_) => match verdict.into_string() {
+                        Ok(reason) => reason,
+                        Err(other) => format!("unexpected result of type {other}"),
+                    },
+                },
+                // fail closed: a broken script must not let transactions through
+                Err(e) => {
+                    warn!(script = script.name(), "script error: {e}");
+                    format!("error: {e}")
+                }
+            };
+
+            return Err(Rejected {
+                script: script.name(),
+                reason,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tempdir::TempDir;
+    use crate::transaction::TransactionType;
+
+    #[test]
+    /// scripts accept or reject with a reason, changed scripts are picked up when hot reloading
+    fn validate_and_reload() {
+        let dir = TempDir::new("script");
+        let path = dir.join("large.rhai");
+        fs::write(
+            &path,
+            r#"
+fn validate(tx, account) {
+    if tx.type == "withdrawal" && tx.amount > account.available / 2.0 {
+        return "withdrawal of more than half the funds";
+    }
+}
+"#,
+        )
+        .unwrap();
+
+        let mut scripts = Scripts::load(&[&path]).unwrap();
+        scripts.set_hot_reload(true);
+
+        let mut account = Account::new(1);
+        account.available.0 = 100.0;
+
+        let withdrawal = |amount| Transaction::new(TransactionType::Withdrawal, 1, 1, Some(amount), false);
+        assert!(scripts.validate(&withdrawal(10.0), &account).is_ok());
+        assert!(scripts.validate(&Transaction::new(TransactionType::Dispute, 1, 1, None, false), &account).is_ok());
+        let rejected = scripts.validate(&withdrawal(60.0), &account).unwrap_err();
+        assert_eq!(rejected.reason, "withdrawal of more than half the funds");
+
+        // an edit that does not compile keeps the last version
+        fs::write(&path, "fn validate(tx, account) {").unwrap();
+        scripts.scripts[0].modified = None;
+        scripts.checked -= RELOAD_INTERVAL;
+        assert!(scripts.validate(&withdrawal(60.0), &account).is_err());
+        assert!(scripts.validate(&withdrawal(10.0), &account).is_ok());
+
+        fs::write(&path, "fn validate(tx, account) { tx.amount < 5.0 }").unwrap();
+        scripts.scripts[0].modified = None;
+        scripts.checked -= RELOAD_INTERVAL;
+        assert_eq!(scripts.validate(&withdrawal(10.0), &account).unwrap_err().reason, "rejected");
+
+        fs::write(&path, "fn check(tx) {}").unwrap();
+        assert!(matches!(Scripts::load(&[&path]), Err(Error::Entry(..))));
+    }
+}
@@ -0,0 +1,383 @@
+use clap::ValueEnum;
+use csv::WriterBuilder;
+use serde::Serialize;
+use std::io::Write;
+
+use crate::account::{Account, Adjustment, Balance, HistoryEntry, MoneyAggregate};
+use crate::transaction::{AccountId, Timestamp, TransactionId, TransactionType};
+
+/// Output formats of a statement
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub(crate) enum Format {
+    Csv,
+    Json,
+    Text,
+}
+
+/// Period of a statement, bounds are inclusive and any of them can be omitted
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Period {
+    pub(crate) from_tx: Option<TransactionId>,
+    pub(crate) to_tx: Option<TransactionId>,
+    pub(crate) from_time: Option<Timestamp>,
+    pub(crate) to_time: Option<Timestamp>,
+}
+
+impl Period {
+    fn has_tx(&self) -> bool {
+        self.from_tx.is_some() || self.to_tx.is_some()
+    }
+
+    fn has_time(&self) -> bool {
+        self.from_time.is_some() || self.to_time.is_some()
+    }
+
+    /// Entry can start or end the period, steps of disputes carry the id of an earlier transaction
+    /// and only fall into a transaction period between its bounds
+    fn bounds(&self, entry: &HistoryEntry) -> bool {
+        self.contains(entry) && !(self.has_tx() && entry.transaction_type.is_dispute_step())
+    }
+
+    /// Entry falls into the period, entries without timestamp never fall into a time period
+    fn contains(&self, entry: &HistoryEntry) -> bool {
+        let tx = self.from_tx.is_none_or(|from| entry.id >= from)
+            && self.to_tx.is_none_or(|to| entry.id <= to);
+
+        let time = !self.has_time()
+            || entry.timestamp.is_some_and(|t| {
+                self.from_time.is_none_or(|from| t >= from) && self.to_time.is_none_or(|to| t <= to)
+            });
+
+        tx && time
+    }
+
+    /// Entry comes before the period starts
+    fn precedes(&self, entry: &HistoryEntry) -> bool {
+        self.from_tx.is_some_and(|from| entry.id < from)
+            || matches!((self.from_time, entry.timestamp), (Some(from), Some(t)) if t < from)
+    }
+}
+
+/// Statement line is a transaction with the running balances right after it
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct Line {
+    tx: TransactionId,
+    #[serde(rename = "type")]
+    transaction_type: TransactionType,
+    timestamp: Option<Timestamp>,
+    amount: MoneyAggregate,
+    fee: MoneyAggregate,
+    #[serde(flatten)]
+    balance: Balance,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    adjustment: Option<Adjustment>,
+}
+
+impl From<&HistoryEntry> for Line {
+    fn from(entry: &HistoryEntry) -> Self {
+        Self {
+            tx: entry.id,
+            transaction_type: entry.transaction_type,
+            timestamp: entry.timestamp,
+            amount: MoneyAggregate(entry.amount),
+            fee: MoneyAggregate(entry.fee),
+            balance: entry.balance,
+            adjustment: entry.adjustment.clone(),
+        }
+    }
+}
+
+/// Client account statement for a period: opening balance, transactions with running balances, closing balance
+/// and manual adjustments among the transactions listed once more on their own
+#[derive(Debug, Serialize)]
+pub(crate) struct Statement {
+    client: AccountId,
+    opening: Balance,
+    lines: Vec<Line>,
+    closing: Balance,
+    adjustments: Vec<Line>,
+}
+
+/// Flat csv record, opening and closing rows have no transaction columns
+#[derive(Serialize)]
+struct Row<'a> {
+    entry: &'a str,
+    tx: Option<TransactionId>,
+    #[serde(rename = "type")]
+    transaction_type: Option<TransactionType>,
+    timestamp: Option<Timestamp>,
+    amount: Option<MoneyAggregate>,
+    fee: Option<MoneyAggregate>,
+    available: MoneyAggregate,
+    held: MoneyAggregate,
+    total: MoneyAggregate,
+    operator: Option<&'a str>,
+    reason: Option<&'a str>,
+}
+
+impl<'a> Row<'a> {
+    fn balance(entry: &'a str, balance: &Balance) -> Self {
+        Self {
+            entry,
+            tx: None,
+            transaction_type: None,
+            timestamp: None,
+            amount: None,
+            fee: None,
+            available: balance.available,
+            held: balance.held,
+            total: balance.total,
+            operator: None,
+            reason: None,
+        }
+    }
+
+    fn line(line: &'a Line) -> Self {
+        Self {
+            entry: if line.adjustment.is_some() { "adjustment" } else { "transaction" },
+            tx: Some(line.tx),
+            transaction_type: Some(line.transaction_type),
+            timestamp: line.timestamp,
+            amount: Some(line.amount),
+            fee: Some(line.fee),
+            operator: line.adjustment.as_ref().map(|a| a.operator.as_str()),
+            reason: line.adjustment.as_ref().map(|a| a.reason.as_str()),
+            ..Self::balance("", &line.balance)
+        }
+    }
+}
+
+impl Statement {
+    /// Select the slice of account history from the first to the last entry within the period,
+    /// opening balance is the one before the first selected transaction
+    pub(crate) fn new(account: &Account, period: &Period) -> Self {
+        let history = account.history();
+
+        let first = history.iter().position(|e| period.bounds(e));
+        let last = history.iter().rposition(|e| period.bounds(e));
+
+        let (opening, lines) = match first.zip(last) {
+            Some((first, last)) => {
+                let opening = first.checked_sub(1).map(|i| history[i].balance).unwrap_or_default();
+                let lines = history[first..=last].iter().map(Line::from).collect();
+                (opening, lines)
+            }
+            None => {
+                // nothing happened in the period, carry over the balance from before it
+                let opening = history.iter().rev().find(|e| period.precedes(e)).map(|e| e.balance).unwrap_or_default();
+                (opening, Vec::new())
+            }
+        };
+
+        let closing = lines.last().map_or(opening, |l: &Line| l.balance);
+        let adjustments = lines.iter().filter(|l| l.adjustment.is_some()).cloned().collect();
+
+        Self {
+            client: account.id(),
+            opening,
+            lines,
+            closing,
+            adjustments,
+        }
+    }
+
+    /// Serialize statement into writer in the given format
+    pub(crate) fn write<W>(&self, wtr: W, format: Format) -> anyhow::Result<()>
+    where
+        W: Write,
+    {
+        match format {
+            Format::Csv => self.write_csv(wtr),
+            Format::Json => self.write_json(wtr),
+            Format::Text => self.write_text(wtr),
+        }
+    }
+
+    fn write_csv<W: Write>(&self, wtr: W) -> anyhow::Result<()> {
+        let mut writer = WriterBuilder::new().from_writer(wtr);
+
+        writer.serialize(Row::balance("opening", &self.opening))?;
+        for line in self.lines.iter() {
+            writer.serialize(Row::line(line))?;
+        }
+        writer.serialize(Row::balance("closing", &self.closing))?;
+
+        writer.flush()?;
+        Ok(())
+    }
+
+    fn write_json<W: Write>(&self, mut wtr: W) -> anyhow::Result<()> {
+        serde_json::to_writer_pretty(&mut wtr, self)?;
+        writeln!(wtr)?;
+        Ok(())
+    }
+
+    fn write_text<W: Write>(&self, mut wtr: W) -> anyhow::Result<()> {
+        let money = |m: MoneyAggregate| format!("{:.4}", m.rounded());
+
+        writeln!(wtr, "Statement for client {}", self.client)?;
+        writeln!(
+            wtr,
+            "{:<12} {:>10} {:<14} {:>14} {:>14} {:>14} {:>14} {:>14}",
+            "", "tx", "type", "amount", "fee", "available", "held", "total"
+        )?;
+
+        let balance_row = |wtr: &mut W, title: &str, b: &Balance| {
+            writeln!(
+                wtr,
+                "{:<12} {:>10} {:<14} {:>14} {:>14} {:>14} {:>14} {:>14}",
+                title, "", "", "", "", money(b.available), money(b.held), money(b.total)
+            )
+        };
+
+        balance_row(&mut wtr, "Opening", &self.opening)?;
+        for line in self.lines.iter() {
+            writeln!(
+                wtr,
+                "{:<12} {:>10} {:<14} {:>14} {:>14} {:>14} {:>14} {:>14}",
+                "",
+                line.tx,
+                line.transaction_type.name(),
+                money(line.amount),
+                money(line.fee),
+                money(line.balance.available),
+                money(line.balance.held),
+                money(line.balance.total)
+            )?;
+        }
+        balance_row(&mut wtr, "Closing", &self.closing)?;
+
+        if !self.adjustments.is_empty() {
+            writeln!(wtr)?;
+            writeln!(wtr, "Adjustments")?;
+            writeln!(wtr, "{:<12} {:>10} {:<14} {:>14} {:<16} {:<16}", "", "tx", "type", "amount", "operator", "reason")?;
+            for line in self.adjustments.iter() {
+                let Some(adjustment) = &line.adjustment else { continue };
+                writeln!(
+                    wtr,
+                    "{:<12} {:>10} {:<14} {:>14} {:<16} {:<16}",
+                    "",
+                    line.tx,
+                    line.transaction_type.name(),
+                    money(line.amount),
+                    adjustment.operator,
+                    adjustment.reason
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EngineConfig;
+    use crate::engine::Engine;
+
+    const CSV: &str = "\
+type,       client, tx, amount, timestamp
+deposit,         1,  1,    2.0, 100
+deposit,         2,  2,    5.0, 150
+deposit,         1,  3,    3.0, 200
+dispute,         1,  1,       , 300
+withdrawal,      1,  4,    1.5, 400
+resolve,         1,  1,       , 500
+";
+
+    fn statement(period: Period) -> Statement {
+        let mut engine = Engine::new(EngineConfig::default());
+        engine.input(CSV.as_bytes()).unwrap();
+        Statement::new(engine.account(1).unwrap(), &period)
+    }
+
+    #[test]
+    /// whole history opens at zero and closes at the current balance
+    fn full_history() {
+        let s = statement(Period::default());
+
+        assert_eq!(s.opening, Balance::default());
+        assert_eq!(s.lines.iter().map(|l| l.tx).collect::<Vec<_>>(), vec![1, 3, 1, 4, 1]);
+        assert_eq!(s.closing.available.0, 3.5);
+        assert_eq!(s.closing.held.0, 0.0);
+        assert_eq!(s.closing.total.0, 3.5);
+    }
+
+    #[test]
+    /// time period opens with the balance before its first transaction
+    fn time_period() {
+        let s = statement(Period {
+            from_time: Some(250),
+            to_time: Some(400),
+            ..Period::default()
+        });
+
+        assert_eq!(s.opening.available.0, 5.0);
+        assert_eq!(s.lines.len(), 2);
+        assert_eq!(s.lines[0].balance.held.0, 2.0);
+        assert_eq!(s.closing.available.0, 1.5);
+        assert_eq!(s.closing.held.0, 2.0);
+        assert_eq!(s.closing.total.0, 3.5);
+    }
+
+    #[test]
+    /// dispute of an earlier transaction between the bounds of a transaction period is in it, the ones after are not
+    fn dispute_in_tx_period() {
+        let s = statement(Period {
+            from_tx: Some(3),
+            to_tx: Some(4),
+            ..Period::default()
+        });
+
+        let lines: Vec<_> = s.lines.iter().map(|l| (l.tx, l.transaction_type)).collect();
+        assert_eq!(lines, [(3, TransactionType::Deposit), (1, TransactionType::Dispute), (4, TransactionType::Withdrawal)]);
+        assert_eq!(s.opening.available.0, 2.0);
+        assert_eq!(s.closing.available.0, 1.5);
+        assert_eq!(s.closing.held.0, 2.0);
+
+        let s = statement(Period {
+            to_tx: Some(1),
+            ..Period::default()
+        });
+        assert_eq!(s.lines.len(), 1);
+        assert_eq!(s.closing.total.0, 2.0);
+    }
+
+    #[test]
+    /// empty period carries the balance over from before it
+    fn empty_period() {
+        let s = statement(Period {
+            from_tx: Some(10),
+            ..Period::default()
+        });
+
+        assert!(s.lines.is_empty());
+        assert_eq!(s.opening, s.closing);
+        assert_eq!(s.closing.total.0, 3.5);
+    }
+
+    #[test]
+    fn write_csv() {
+        let s = statement(Period {
+            from_tx: Some(3),
+            to_tx: Some(4),
+            ..Period::default()
+        });
+
+        let mut out = vec![];
+        s.write(&mut out, Format::Csv).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\
+entry,tx,type,timestamp,amount,fee,available,held,total,operator,reason
+opening,,,,,,2.0,0.0,2.0,,
+transaction,3,deposit,200,3.0,0.0,5.0,0.0,5.0,,
+transaction,1,dispute,300,2.0,0.0,3.0,2.0,5.0,,
+transaction,4,withdrawal,400,1.5,0.0,1.5,2.0,3.5,,
+closing,,,,,,1.5,2.0,3.5,,
+"
+        );
+    }
+}
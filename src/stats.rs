@@ -0,0 +1,111 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+use crate::account::{HistoryEntry, MoneyAggregate};
+use crate::transaction::TransactionType;
+
+/// Counters of one transaction type
+#[derive(Debug, Default, Serialize)]
+pub(crate) struct TypeStats {
+    pub(crate) accepted: usize,
+    /// Rejected transactions by account error variant
+    pub(crate) rejected: BTreeMap<&'static str, usize>,
+}
+
+/// Processing statistics collected by the engine over all inputs
+#[derive(Debug, Default, Serialize)]
+pub(crate) struct Stats {
+    /// Records read from input, including the ones that cannot be parsed
+    pub(crate) records: usize,
+    /// Records that cannot be parsed by csv error kind, their type is unknown
+    pub(crate) parse_errors: BTreeMap<&'static str, usize>,
+    pub(crate) transactions: BTreeMap<TransactionType, TypeStats>,
+    pub(crate) deposited: MoneyAggregate,
+    pub(crate) withdrawn: MoneyAggregate,
+    /// Amounts moved into held by disputes
+    pub(crate) held: MoneyAggregate,
+    pub(crate) charged_back: MoneyAggregate,
+    /// Fees charged to clients
+    pub(crate) fees: MoneyAggregate,
+    /// Interest booked, paid to clients less charged for overdrafts
+    pub(crate) interest: MoneyAggregate,
+    /// Risk rule hits by rule name, including the ones that let the transaction through
+    pub(crate) risk_hits: BTreeMap<String, usize>,
+    pub(crate) accounts_created: usize,
+    pub(crate) accounts_locked: usize,
+    pub(crate) accounts_flagged: usize,
+    pub(crate) elapsed_secs: f64,
+    /// Records per second
+    pub(crate) throughput: f64,
+}
+
+impl Stats {
+    pub(crate) fn parse_error(&mut self, kind: &'static str) {
+        *self.parse_errors.entry(kind).or_default() += 1;
+    }
+
+    pub(crate) fn accepted(&mut self, entry: &HistoryEntry) {
+        self.transactions.entry(entry.transaction_type).or_default().accepted += 1;
+        self.fees.0 += entry.fee;
+
+        let volume = match entry.transaction_type {
+            TransactionType::Deposit => &mut self.deposited,
+            TransactionType::Withdrawal => &mut self.withdrawn,
+            TransactionType::Dispute => &mut self.held,
+            TransactionType::Chargeback => &mut self.charged_back,
+            TransactionType::Interest => &mut self.interest,
+            TransactionType::Resolve | TransactionType::AdjustCredit | TransactionType::AdjustDebit | TransactionType::Custom(..) => return,
+        };
+        volume.0 += entry.amount;
+    }
+
+    pub(crate) fn rejected(&mut self, transaction_type: TransactionType, kind: &'static str) {
+        let stats = self.transactions.entry(transaction_type).or_default();
+        *stats.rejected.entry(kind).or_default() += 1;
+    }
+
+    pub(crate) fn risk_hit(&mut self, rule: &str) {
+        *self.risk_hits.entry(rule.to_string()).or_default() += 1;
+    }
+
+    /// Add time spent processing and recalculate throughput
+    pub(crate) fn elapsed(&mut self, elapsed: Duration) {
+        self.elapsed_secs += elapsed.as_secs_f64();
+        if self.elapsed_secs > 0.0 {
+            self.throughput = self.records as f64 / self.elapsed_secs;
+        }
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "records: {}", self.records)?;
+        for (kind, count) in self.parse_errors.iter() {
+            writeln!(f, "  parse {kind}: {count}")?;
+        }
+
+        for (transaction_type, stats) in self.transactions.iter() {
+            writeln!(f, "{transaction_type:?}: accepted {}", stats.accepted)?;
+            for (kind, count) in stats.rejected.iter() {
+                writeln!(f, "  rejected {kind}: {count}")?;
+            }
+        }
+
+        writeln!(f, "deposited: {}", self.deposited.rounded())?;
+        writeln!(f, "withdrawn: {}", self.withdrawn.rounded())?;
+        writeln!(f, "held: {}", self.held.rounded())?;
+        writeln!(f, "charged back: {}", self.charged_back.rounded())?;
+        writeln!(f, "fees: {}", self.fees.rounded())?;
+        writeln!(f, "interest: {}", self.interest.rounded())?;
+        for (rule, count) in self.risk_hits.iter() {
+            writeln!(f, "risk rule {rule}: {count}")?;
+        }
+        writeln!(f, "accounts created: {}", self.accounts_created)?;
+        writeln!(f, "accounts locked: {}", self.accounts_locked)?;
+        writeln!(f, "accounts flagged: {}", self.accounts_flagged)?;
+        writeln!(f, "elapsed: {:.3}s", self.elapsed_secs)?;
+        writeln!(f, "throughput: {:.0} records/s", self.throughput)
+    }
+}
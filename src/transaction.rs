@@ -1,4 +1,5 @@
-use serde::Deserialize;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
 
 /// Client Account id
 pub type AccountId = u16;
@@ -6,9 +7,14 @@ pub type AccountId = u16;
 pub type TransactionId = u32;
 /// Amounts with no restriction to serialized precision
 pub type Money = f64;
+/// Seconds since the unix epoch
+pub type Timestamp = u64;
+
+/// Ids of transactions generated by the engine are allocated downwards from here to stay clear of input ids
+pub(crate) const SYSTEM_TX_START: TransactionId = TransactionId::MAX;
 
 /// Transaction is applied to a client account
-#[derive(Debug, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
 pub struct Transaction {
     /// Transaction id is `tx` in the input
     #[serde(rename = "tx")]
@@ -16,16 +22,45 @@ pub struct Transaction {
     /// Client account id is `client` in the input
     #[serde(rename = "client")]
     pub(crate) account_id: AccountId,
-    /// Transaction type is `type` in the input
-    #[serde(rename = "type")]
+    /// Transaction type is `type` in the input, a name that is not built-in is taken for a custom type
+    #[serde(rename = "type", deserialize_with = "TransactionType::deserialize_input")]
     pub(crate) transaction_type: TransactionType,
     /// Amount is optional in Dispute, Resolve, Chargeback transactions
     pub(crate) amount: Option<Money>,
+    /// Optional `timestamp` column, used to select statement periods
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) timestamp: Option<Timestamp>,
+    /// Operator making a manual adjustment, optional `operator` column
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) operator: Option<String>,
+    /// Reason code of a manual adjustment, optional `reason` column
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) reason: Option<String>,
     #[serde(skip)]
     pub(crate) disputed: bool,
 }
 
 impl Transaction {
+    pub fn id(&self) -> TransactionId {
+        self.id
+    }
+
+    pub fn client(&self) -> AccountId {
+        self.account_id
+    }
+
+    pub fn transaction_type(&self) -> TransactionType {
+        self.transaction_type
+    }
+
+    pub fn amount(&self) -> Option<Money> {
+        self.amount
+    }
+
+    pub fn timestamp(&self) -> Option<Timestamp> {
+        self.timestamp
+    }
+
     #[cfg(test)]
     pub(crate) fn new(
         transaction_type: TransactionType,
@@ -39,14 +74,16 @@ impl Transaction {
             account_id: client,
             id: tx,
             amount,
+            timestamp: None,
+            operator: None,
+            reason: None,
             disputed,
         }
     }
 }
 
-#[derive(Debug, Deserialize, PartialEq)]
-#[serde(rename_all = "lowercase")]
-pub(crate) enum TransactionType {
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TransactionType {
     /// Credit to the client's asset account, meaning it should increase the available and total funds of the client account
     Deposit,
     /// Debit to the client's asset account, meaning it should decrease the available and total funds of the client account
@@ -57,6 +94,147 @@ pub(crate) enum TransactionType {
     Resolve,
     /// Final state of a dispute and represents the client reversing a transaction
     Chargeback,
+    /// Interest booked by the engine itself, negative for overdrafts, never read from input
+    Interest,
+    /// Manual credit by an operator with a reason code, never disputable
+    AdjustCredit,
+    /// Manual debit by an operator with a reason code, never disputable
+    AdjustDebit,
+    /// Type registered by name with a handler of its balance effects in an engine
+    Custom(CustomName),
+}
+
+/// Longest name of a custom type in bytes
+const CUSTOM_NAME_MAX: usize = 32;
+
+/// Name of a custom type kept inline so `TransactionType` stays a copyable value
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CustomName {
+    /// Name padded with zeros, ordered before `len` so names sort like strings
+    bytes: [u8; CUSTOM_NAME_MAX],
+    len: u8,
+}
+
+impl CustomName {
+    fn new(name: &str) -> Option<Self> {
+        if name.is_empty() || name.len() > CUSTOM_NAME_MAX {
+            return None;
+        }
+
+        let mut bytes = [0; CUSTOM_NAME_MAX];
+        bytes[..name.len()].copy_from_slice(name.as_bytes());
+        Some(Self {
+            bytes,
+            len: name.len() as u8,
+        })
+    }
+
+    fn as_str(&self) -> &str {
+        std::str::from_utf8(&self.bytes[..self.len as usize]).expect("custom names are copied from strings")
+    }
+}
+
+impl TransactionType {
+    /// Built-in types read from input
+    const INPUT: [TransactionType; 7] = [
+        TransactionType::Deposit,
+        TransactionType::Withdrawal,
+        TransactionType::Dispute,
+        TransactionType::Resolve,
+        TransactionType::Chargeback,
+        TransactionType::AdjustCredit,
+        TransactionType::AdjustDebit,
+    ];
+
+    /// Name in the `type` column
+    pub fn name(&self) -> &str {
+        match self {
+            TransactionType::Deposit => "deposit",
+            TransactionType::Withdrawal => "withdrawal",
+            TransactionType::Dispute => "dispute",
+            TransactionType::Resolve => "resolve",
+            TransactionType::Chargeback => "chargeback",
+            TransactionType::Interest => "interest",
+            TransactionType::AdjustCredit => "adjust_credit",
+            TransactionType::AdjustDebit => "adjust_debit",
+            TransactionType::Custom(name) => name.as_str(),
+        }
+    }
+
+    /// Custom type with a name, applied by an engine only once it has a handler registered for it,
+    /// built-in names are taken
+    pub(crate) fn custom(name: &str) -> Result<Self, String> {
+        if Self::INPUT.iter().chain([&TransactionType::Interest]).any(|t| t.name() == name) {
+            return Err(format!("{name} is a built-in transaction type"));
+        }
+
+        CustomName::new(name)
+            .map(TransactionType::Custom)
+            .ok_or_else(|| format!("custom type name {name:?} must have 1 to {CUSTOM_NAME_MAX} bytes"))
+    }
+
+    /// Step of a dispute, carries the id of the disputed transaction instead of its own
+    pub(crate) fn is_dispute_step(&self) -> bool {
+        matches!(self, TransactionType::Dispute | TransactionType::Resolve | TransactionType::Chargeback)
+    }
+
+    /// Manual adjustment by an operator
+    pub(crate) fn is_adjustment(&self) -> bool {
+        matches!(self, TransactionType::AdjustCredit | TransactionType::AdjustDebit)
+    }
+
+    /// Built-in type read from input
+    fn parse(name: &str) -> Option<Self> {
+        Self::INPUT.into_iter().find(|t| t.name() == name)
+    }
+
+    /// Type of a transaction read from input, any name that is not built-in is a custom type
+    /// left to the engine to resolve against its handlers
+    fn deserialize_input<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Self::parse(&name)
+            .or_else(|| Self::custom(&name).ok())
+            .ok_or_else(|| de::Error::unknown_variant(&name, BUILT_IN))
+    }
+}
+
+/// Names of the built-in types read from input
+const BUILT_IN: &[&str] = &["deposit", "withdrawal", "dispute", "resolve", "chargeback", "adjust_credit", "adjust_debit"];
+
+impl fmt::Debug for TransactionType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransactionType::Deposit => f.write_str("Deposit"),
+            TransactionType::Withdrawal => f.write_str("Withdrawal"),
+            TransactionType::Dispute => f.write_str("Dispute"),
+            TransactionType::Resolve => f.write_str("Resolve"),
+            TransactionType::Chargeback => f.write_str("Chargeback"),
+            TransactionType::Interest => f.write_str("Interest"),
+            TransactionType::AdjustCredit => f.write_str("AdjustCredit"),
+            TransactionType::AdjustDebit => f.write_str("AdjustDebit"),
+            TransactionType::Custom(name) => f.write_str(name.as_str()),
+        }
+    }
+}
+
+impl fmt::Display for TransactionType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl Serialize for TransactionType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+/// Built-in types only, as in config files
+impl<'de> Deserialize<'de> for TransactionType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Self::parse(&name).ok_or_else(|| de::Error::unknown_variant(&name, BUILT_IN))
+    }
 }
 
 #[cfg(test)]
@@ -92,4 +270,30 @@ chargeback,      1,  1,
             assert_eq!(r, *e);
         }
     }
+
+    #[test]
+    /// input takes any other name for a custom type, config only built-in ones, interest is never read
+    fn custom_types() {
+        let parse = |name: &str| serde_json::from_str::<TransactionType>(&format!("\"{name}\""));
+        let read = |name: &str| {
+            let csv = format!("type,client,tx,amount\n{name},1,1,1.0\n");
+            let mut reader = ReaderBuilder::new().from_reader(csv.as_bytes());
+            reader.deserialize::<Transaction>().next().unwrap().map(|t| t.transaction_type)
+        };
+
+        let cashback = TransactionType::custom("cashback").unwrap();
+        assert_eq!(TransactionType::custom("cashback").unwrap(), cashback);
+        assert_eq!(read("cashback").unwrap(), cashback);
+        assert!(parse("cashback").is_err());
+        assert_eq!(read("deposit").unwrap(), TransactionType::Deposit);
+        assert_eq!(serde_json::to_string(&cashback).unwrap(), "\"cashback\"");
+        assert_eq!(cashback.to_string(), "cashback");
+        assert!(TransactionType::custom("bonus").unwrap() < cashback);
+
+        assert!(TransactionType::custom("deposit").is_err());
+        assert!(TransactionType::custom("interest").is_err());
+        assert!(TransactionType::custom(&"x".repeat(CUSTOM_NAME_MAX + 1)).is_err());
+        assert!(parse("interest").is_err());
+        assert!(read("interest").is_err());
+    }
 }
@@ -0,0 +1,89 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::engine::Rejection;
+
+/// Counts of records that would be accepted and rejected, grouped by reason
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Summary {
+    pub(crate) records: usize,
+    /// Rejected records by stage `parse` or `apply` and the kind of error
+    pub(crate) rejects: BTreeMap<(&'static str, &'static str), usize>,
+}
+
+impl Summary {
+    /// Count a rejected record under its stage and kind
+    pub(crate) fn reject(&mut self, rejection: &Rejection) {
+        *self.rejects.entry((rejection.stage(), rejection.kind())).or_default() += 1;
+    }
+
+    pub(crate) fn rejected(&self) -> usize {
+        self.rejects.values().sum()
+    }
+
+    pub(crate) fn accepted(&self) -> usize {
+        self.records - self.rejected()
+    }
+
+    /// Fraction of rejected records, zero for empty input
+    pub(crate) fn reject_rate(&self) -> f64 {
+        if self.records == 0 {
+            return 0.0;
+        }
+        self.rejected() as f64 / self.records as f64
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "records:  {}", self.records)?;
+        writeln!(f, "accepted: {}", self.accepted())?;
+        writeln!(f, "rejected: {} ({:.2}%)", self.rejected(), self.reject_rate() * 100.0)?;
+
+        for ((stage, kind), count) in self.rejects.iter() {
+            writeln!(f, "  {stage} {kind}: {count}")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EngineConfig;
+    use crate::dialect::Dialect;
+    use crate::engine::Engine;
+
+    #[test]
+    /// rejections are grouped by stage and kind
+    fn summary() {
+        let csv = "\
+type,       client, tx, amount
+deposit,         1,  1,    1.0
+deposit,         1,  2,    abc
+transfer,        1,  3,    1.0
+withdrawal,      1,  4,    2.0
+withdrawal,      1,  5,    2.0
+dispute,         1,  9,
+deposit,         2,  6,    1.0
+";
+
+        let mut summary = Summary::default();
+        let mut engine = Engine::new(EngineConfig::default());
+        summary.records = engine.input_with(csv.as_bytes(), &Dialect::default(), |_, rejection| summary.reject(&rejection)).unwrap();
+
+        assert_eq!(summary.records, 7);
+        assert_eq!(summary.accepted(), 2);
+        assert_eq!(
+            summary.rejects,
+            BTreeMap::from([
+                (("apply", "InsufficientFunds"), 2),
+                (("apply", "TransactionNotFound"), 1),
+                (("parse", "InvalidValue"), 1),
+                (("parse", "ParseFloat"), 1),
+            ])
+        );
+        assert_eq!(summary.reject_rate(), 5.0 / 7.0);
+    }
+}
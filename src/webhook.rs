@@ -0,0 +1,592 @@
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, error, warn};
+
+use crate::atomic::AtomicFile;
+use crate::event::{Event, EventKind, EventSink};
+
+/// Header with the hex HMAC-SHA256 of the payload keyed with the secret of the webhook
+pub(crate) const SIGNATURE_HEADER: &str = "X-Signature-256";
+
+/// Header with the id of a delivery, the same on retries so receivers can drop duplicates
+pub(crate) const DELIVERY_HEADER: &str = "X-Delivery-Id";
+
+/// Wait before the first retry of a failed delivery, doubled on every further failure
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Longest wait between retries
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// Failed attempts after which a delivery is moved to the dead letters, about a day with the backoff above
+const MAX_ATTEMPTS: u32 = 300;
+
+/// Longest the end of processing waits for retries of deliveries left
+const FINISH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often the outbox is checked for deliveries due
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Longest a receiver may take to answer a delivery
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// HTTP endpoint notified of account events in serve mode
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Webhook {
+    pub(crate) url: String,
+    /// Key payloads are signed with
+    pub(crate) secret: String,
+    /// Events posted to the webhook
+    #[serde(default = "default_events")]
+    pub(crate) events: Vec<EventKind>,
+}
+
+fn default_events() -> Vec<EventKind> {
+    vec![EventKind::AccountLocked, EventKind::ChargebackApplied, EventKind::FundsHeld]
+}
+
+impl Webhook {
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if !self.url.starts_with("http://") && !self.url.starts_with("https://") {
+            return Err(format!("url {} must be http or https", self.url));
+        }
+        if self.secret.is_empty() {
+            return Err(format!("secret of {} must not be empty", self.url));
+        }
+        if self.events.is_empty() {
+            return Err(format!("{} must subscribe to some events", self.url));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum Error {
+    #[error("cannot read webhook outbox: {0}")]
+    Read(#[from] io::Error),
+
+    #[error("cannot parse webhook outbox: {0}")]
+    Parse(#[from] serde_json::Error),
+}
+
+/// Event waiting to be posted to a webhook
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Delivery {
+    id: u64,
+    url: String,
+    /// Json of the event, signed when posted
+    payload: String,
+    /// Failed attempts so far
+    attempts: u32,
+    /// Unix time in milliseconds of the next attempt
+    next_attempt: u64,
+}
+
+/// Change of the outbox, appended to its file as a json line
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Record {
+    /// Highest delivery id handed out so far, kept when compaction drops the deliveries themselves
+    NextId { id: u64 },
+    Queued(Delivery),
+    Delivered { id: u64 },
+    Failed { id: u64, next_attempt: u64 },
+    DeadLettered { id: u64 },
+}
+
+/// Deliveries not acknowledged by their webhook yet in queue order and the ones given up on
+#[derive(Debug, Default)]
+struct Outbox {
+    next_id: u64,
+    deliveries: Vec<Delivery>,
+    /// Deliveries failed `MAX_ATTEMPTS` times, kept in the file and never retried
+    dead_letters: Vec<Delivery>,
+}
+
+impl Outbox {
+    /// Replay the records of the outbox file, a missing file is an empty outbox
+    fn load(path: &Path) -> Result<Self, Error> {
+        let mut outbox = Self::default();
+
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(outbox),
+            Err(e) => return Err(e.into()),
+        };
+
+        // a record cut short by a crash while appending has no line end, the event it was queued for never completed
+        let complete = contents.rfind('\n').map_or("", |end| &contents[..end]);
+        for line in complete.lines() {
+            outbox.apply(serde_json::from_str(line)?);
+        }
+
+        Ok(outbox)
+    }
+
+    fn apply(&mut self, record: Record) {
+        match record {
+            Record::NextId { id } => self.next_id = self.next_id.max(id),
+            Record::Queued(delivery) => {
+                self.next_id = self.next_id.max(delivery.id);
+                self.deliveries.push(delivery);
+            }
+            Record::Delivered { id } => self.deliveries.retain(|d| d.id != id),
+            Record::Failed { id, next_attempt } => {
+                if let Some(delivery) = self.deliveries.iter_mut().find(|d| d.id == id) {
+                    delivery.attempts += 1;
+                    delivery.next_attempt = next_attempt;
+                }
+            }
+            Record::DeadLettered { id } => {
+                if let Some(i) = self.deliveries.iter().position(|d| d.id == id) {
+                    let delivery = self.deliveries.remove(i);
+                    self.dead_letters.push(delivery);
+                }
+            }
+        }
+    }
+
+    /// Earliest time a delivery can be attempted, that of the first delivery of each webhook
+    fn next_attempt(&self) -> Option<u64> {
+        let mut urls = Vec::new();
+        self.deliveries
+            .iter()
+            .filter(|d| {
+                let first = !urls.contains(&d.url.as_str());
+                urls.push(&d.url);
+                first
+            })
+            .map(|d| d.next_attempt)
+            .min()
+    }
+}
+
+/// Outbox with the file its records are appended to, persisted before an event is done
+/// so notifications survive a restart
+struct Journal {
+    outbox: Outbox,
+    file: File,
+}
+
+impl Journal {
+    /// Load the outbox and replace its file atomically with the last delivery id, the records of the deliveries left
+    /// and the dead letters, so the file only grows by the records of one run and ids are never handed out twice
+    fn open(path: &Path) -> Result<Self, Error> {
+        let outbox = Outbox::load(path)?;
+
+        let dead_letters = outbox
+            .dead_letters
+            .iter()
+            .flat_map(|delivery| [Record::Queued(delivery.clone()), Record::DeadLettered { id: delivery.id }]);
+        let records = [Record::NextId { id: outbox.next_id }]
+            .into_iter()
+            .chain(dead_letters)
+            .chain(outbox.deliveries.iter().cloned().map(Record::Queued));
+
+        let mut wtr = BufWriter::new(AtomicFile::create(path)?);
+        for record in records {
+            serde_json::to_writer(&mut wtr, &record)?;
+            writeln!(wtr)?;
+        }
+        wtr.into_inner().map_err(|e| e.into_error())?.commit()?;
+
+        let file = OpenOptions::new().append(true).open(path)?;
+        Ok(Self { outbox, file })
+    }
+
+    /// Append records in one write synced to disk, then apply them to the outbox
+    fn append(&mut self, records: Vec<Record>) -> io::Result<()> {
+        if records.is_empty() {
+            return Ok(());
+        }
+
+        let mut lines = Vec::new();
+        for record in records.iter() {
+            serde_json::to_writer(&mut lines, record)?;
+            lines.push(b'\n');
+        }
+        self.file.write_all(&lines)?;
+        self.file.sync_data()?;
+
+        for record in records {
+            self.outbox.apply(record);
+        }
+        Ok(())
+    }
+}
+
+/// Webhooks with the outbox of their deliveries, events are queued by the engine and posted by a background thread
+pub(crate) struct Webhooks {
+    webhooks: Vec<Webhook>,
+    journal: Mutex<Journal>,
+    /// Held while posting so deliveries due are not posted twice at once
+    delivering: Mutex<()>,
+    agent: ureq::Agent,
+}
+
+impl Webhooks {
+    /// Resume deliveries left in the outbox file by an earlier run
+    pub(crate) fn open<P: AsRef<Path>>(webhooks: Vec<Webhook>, path: P) -> Result<Self, Error> {
+        let journal = Journal::open(path.as_ref())?;
+
+        Ok(Self {
+            webhooks,
+            journal: Mutex::new(journal),
+            delivering: Mutex::new(()),
+            agent: ureq::AgentBuilder::new().timeout(TIMEOUT).build(),
+        })
+    }
+
+    /// Deliveries not acknowledged yet
+    pub(crate) fn pending(&self) -> usize {
+        self.journal.lock().unwrap().outbox.deliveries.len()
+    }
+
+    /// Deliveries given up on
+    pub(crate) fn dead_letters(&self) -> usize {
+        self.journal.lock().unwrap().outbox.dead_letters.len()
+    }
+
+    /// Queue deliveries of an event to the webhooks subscribed to it, persisted when this returns
+    fn enqueue(&self, event: &Event) -> io::Result<()> {
+        let subscribed: Vec<_> = self.webhooks.iter().filter(|webhook| webhook.events.contains(&event.event)).collect();
+        if subscribed.is_empty() {
+            return Ok(());
+        }
+
+        let payload = serde_json::to_string(event)?;
+        let mut journal = self.journal.lock().unwrap();
+        let next_id = journal.outbox.next_id;
+        let records = subscribed
+            .into_iter()
+            .zip(next_id + 1..)
+            .map(|(webhook, id)| {
+                Record::Queued(Delivery {
+                    id,
+                    url: webhook.url.clone(),
+                    payload: payload.clone(),
+                    attempts: 0,
+                    next_attempt: 0,
+                })
+            })
+            .collect();
+        journal.append(records)
+    }
+
+    /// Post deliveries due at `now` in unix milliseconds, every webhook gets its deliveries strictly in queue order:
+    /// a failed one is retried with exponential backoff before any later one is posted
+    /// and moved to the dead letters after `MAX_ATTEMPTS`, returns the number delivered
+    pub(crate) fn deliver_due(&self, now: u64) -> io::Result<usize> {
+        let _delivering = self.delivering.lock().unwrap();
+
+        // post without holding the outbox so the engine keeps queueing meanwhile
+        let queued = self.journal.lock().unwrap().outbox.deliveries.clone();
+
+        let mut records = Vec::new();
+        // webhooks waiting for an earlier delivery to get through
+        let mut blocked = Vec::new();
+        for delivery in queued {
+            if blocked.contains(&delivery.url) {
+                continue;
+            }
+            if delivery.next_attempt > now {
+                blocked.push(delivery.url);
+                continue;
+            }
+
+            // the webhook may be gone from the config since the delivery was queued
+            let Some(webhook) = self.webhooks.iter().find(|webhook| webhook.url == delivery.url) else {
+                warn!(url = delivery.url, id = delivery.id, "webhook no longer configured, moved to dead letters");
+                records.push(Record::DeadLettered { id: delivery.id });
+                continue;
+            };
+
+            match self.post(webhook, &delivery) {
+                Ok(()) => {
+                    debug!(url = delivery.url, id = delivery.id, "webhook delivered");
+                    records.push(Record::Delivered { id: delivery.id });
+                }
+                Err(e) if delivery.attempts + 1 >= MAX_ATTEMPTS => {
+                    error!(url = delivery.url, id = delivery.id, attempts = delivery.attempts + 1, "webhook delivery failed, moved to dead letters: {e}");
+                    records.push(Record::Failed { id: delivery.id, next_attempt: now });
+                    records.push(Record::DeadLettered { id: delivery.id });
+                }
+                Err(e) => {
+                    let backoff = INITIAL_BACKOFF.saturating_mul(1 << delivery.attempts.min(16)).min(MAX_BACKOFF);
+                    warn!(url = delivery.url, id = delivery.id, attempts = delivery.attempts + 1, "webhook delivery failed: {e}");
+                    records.push(Record::Failed {
+                        id: delivery.id,
+                        next_attempt: now + backoff.as_millis() as u64,
+                    });
+                    blocked.push(delivery.url);
+                }
+            }
+        }
+
+        let delivered = records.iter().filter(|r| matches!(r, Record::Delivered { .. })).count();
+        self.journal.lock().unwrap().append(records)?;
+
+        Ok(delivered)
+    }
+
+    /// Deliver what is left when processing ends, waiting for retries due within `FINISH_TIMEOUT`,
+    /// returns the number left in the outbox for the next run
+    pub(crate) fn finish(&self) -> io::Result<usize> {
+        let deadline = now_millis() + FINISH_TIMEOUT.as_millis() as u64;
+
+        loop {
+            self.deliver_due(now_millis())?;
+
+            let next = self.journal.lock().unwrap().outbox.next_attempt();
+            match next {
+                Some(next) if next <= deadline => thread::sleep(Duration::from_millis(next.saturating_sub(now_millis()))),
+                _ => return Ok(self.pending()),
+            }
+        }
+    }
+
+    fn post(&self, webhook: &Webhook, delivery: &Delivery) -> Result<(), Box<ureq::Error>> {
+        self.agent
+            .post(&webhook.url)
+            .set("Content-Type", "application/json")
+            .set(SIGNATURE_HEADER, &signature(&webhook.secret, &delivery.payload))
+            .set(DELIVERY_HEADER, &delivery.id.to_string())
+            .send_string(&delivery.payload)?;
+        Ok(())
+    }
+}
+
+/// Hex HMAC-SHA256 of payload keyed with secret, prefixed with `sha256=`
+pub(crate) fn signature(secret: &str, payload: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac takes keys of any length");
+    mac.update(payload.as_bytes());
+    let hex: String = mac.finalize().into_bytes().iter().map(|b| format!("{b:02x}")).collect();
+    format!("sha256={hex}")
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64)
+}
+
+/// Post deliveries due in the background until the process exits
+pub(crate) fn spawn(webhooks: Arc<Webhooks>) -> JoinHandle<()> {
+    thread::spawn(move || loop {
+        if let Err(e) = webhooks.deliver_due(now_millis()) {
+            warn!("cannot update webhook outbox: {e}");
+        }
+        thread::sleep(POLL_INTERVAL);
+    })
+}
+
+/// Sink queueing events for webhooks
+pub(crate) struct WebhookSink(pub(crate) Arc<Webhooks>);
+
+impl EventSink for WebhookSink {
+    fn emit(&mut self, event: &Event) -> io::Result<()> {
+        self.0.enqueue(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EngineConfig;
+    use crate::engine::Engine;
+    use crate::tempdir::TempDir;
+    use std::fs;
+    use std::sync::mpsc;
+
+    /// Request received by the stub
+    struct Posted {
+        signature: String,
+        delivery_id: u64,
+        body: String,
+    }
+
+    impl Posted {
+        fn event(&self) -> serde_json::Value {
+            serde_json::from_str::<serde_json::Value>(&self.body).unwrap()["event"].clone()
+        }
+    }
+
+    /// Receiver answering the first `failures` requests with an error and the rest with success,
+    /// hands every request to the channel
+    fn stub_receiver(failures: usize) -> (String, mpsc::Receiver<Posted>) {
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hooks", server.server_addr().to_ip().unwrap());
+
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for (i, mut request) in server.incoming_requests().enumerate() {
+                let header = |name: &'static str| request.headers().iter().find(|h| h.field.equiv(name)).map(|h| h.value.to_string());
+                let signature = header(SIGNATURE_HEADER).unwrap_or_default();
+                let delivery_id = header(DELIVERY_HEADER).and_then(|id| id.parse().ok()).unwrap_or_default();
+                let mut body = String::new();
+                request.as_reader().read_to_string(&mut body).unwrap();
+                let _ = sender.send(Posted { signature, delivery_id, body });
+
+                let status = if i < failures { 500 } else { 200 };
+                request.respond(tiny_http::Response::empty(status)).unwrap();
+            }
+        });
+
+        (url, receiver)
+    }
+
+    /// Engine queueing the events of a chargeback for webhooks
+    fn chargeback(webhooks: &Arc<Webhooks>) {
+        let mut engine = Engine::new(EngineConfig::default());
+        engine.add_event_sink(Box::new(WebhookSink(webhooks.clone())));
+
+        let csv = "\
+type,       client, tx, amount
+deposit,    1,      1,  10.0
+dispute,    1,      1,
+chargeback, 1,      1,
+";
+        engine.input(csv.as_bytes()).unwrap();
+    }
+
+    /// Kinds of the events posted in order
+    fn posted(received: &mpsc::Receiver<Posted>) -> Vec<serde_json::Value> {
+        received.try_iter().map(|posted| posted.event()).collect()
+    }
+
+    #[test]
+    /// subscribed events are persisted in the outbox, posted signed and in order, retried with backoff until acknowledged
+    fn deliveries() {
+        let dir = TempDir::new("webhook");
+        let path = dir.join("outbox.json");
+
+        let (url, received) = stub_receiver(1);
+        let webhook = Webhook {
+            url,
+            secret: "s3cret".to_string(),
+            events: vec![EventKind::ChargebackApplied, EventKind::AccountLocked],
+        };
+        assert!(webhook.validate().is_ok());
+
+        let webhooks = Arc::new(Webhooks::open(vec![webhook.clone()], &path).unwrap());
+        chargeback(&webhooks);
+        assert_eq!(webhooks.pending(), 2);
+
+        // a restart picks up the outbox
+        drop(webhooks);
+        let webhooks = Webhooks::open(vec![webhook.clone()], &path).unwrap();
+        assert_eq!(webhooks.pending(), 2);
+
+        // the first post fails and the second one waits behind it for its backoff
+        let now = now_millis();
+        assert_eq!(webhooks.deliver_due(now).unwrap(), 0);
+        assert_eq!(webhooks.deliver_due(now).unwrap(), 0);
+        assert_eq!(webhooks.deliver_due(now + INITIAL_BACKOFF.as_millis() as u64).unwrap(), 2);
+        assert_eq!(webhooks.pending(), 0);
+        assert_eq!(Outbox::load(&path).unwrap().deliveries.len(), 0);
+
+        // records are appended, reopening compacts them to the last id and the deliveries left
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 6);
+        drop(webhooks);
+        Webhooks::open(vec![webhook.clone()], &path).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{\"next_id\":{\"id\":2}}\n");
+
+        // a record torn by a crash is left out
+        fs::write(&path, "{\"delivered\":{\"id\":1}}\n{\"queued\":{\"id\":2,").unwrap();
+        assert_eq!(Webhooks::open(vec![webhook.clone()], &path).unwrap().pending(), 0);
+
+        let requests: Vec<_> = received.try_iter().collect();
+        let events: Vec<_> = requests.iter().map(Posted::event).collect();
+        assert_eq!(events, ["ChargebackApplied", "ChargebackApplied", "AccountLocked"]);
+        for posted in requests.iter() {
+            assert_eq!(posted.signature, super::signature("s3cret", &posted.body));
+        }
+
+        assert!(Webhook { url: "ftp://host".to_string(), ..webhook }.validate().is_err());
+    }
+
+    #[test]
+    /// delivery ids keep counting up across restarts after the outbox is emptied
+    fn ids_after_restart() {
+        let dir = TempDir::new("webhook-ids");
+        let path = dir.join("outbox.jsonl");
+
+        let (url, received) = stub_receiver(0);
+        let webhook = Webhook {
+            url,
+            secret: "s3cret".to_string(),
+            events: vec![EventKind::ChargebackApplied, EventKind::AccountLocked],
+        };
+
+        let mut ids = Vec::new();
+        for _ in 0..2 {
+            let webhooks = Arc::new(Webhooks::open(vec![webhook.clone()], &path).unwrap());
+            chargeback(&webhooks);
+            assert_eq!(webhooks.deliver_due(now_millis()).unwrap(), 2);
+            ids.extend(received.try_iter().map(|posted| posted.delivery_id));
+        }
+        assert_eq!(ids, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    /// deliveries failing every attempt end up as dead letters and no longer hold back later ones
+    fn dead_letters() {
+        let dir = TempDir::new("webhook-dead");
+        let path = dir.join("outbox.jsonl");
+
+        // nothing listens on a port just released
+        let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let webhook = Webhook {
+            url: format!("http://{addr}/hooks"),
+            secret: "s3cret".to_string(),
+            events: vec![EventKind::ChargebackApplied, EventKind::AccountLocked],
+        };
+
+        let webhooks = Arc::new(Webhooks::open(vec![webhook.clone()], &path).unwrap());
+        chargeback(&webhooks);
+
+        let mut now = now_millis();
+        for _ in 0..2 * MAX_ATTEMPTS {
+            webhooks.deliver_due(now).unwrap();
+            now += MAX_BACKOFF.as_millis() as u64;
+        }
+        assert_eq!((webhooks.pending(), webhooks.dead_letters()), (0, 2));
+
+        drop(webhooks);
+        let webhooks = Webhooks::open(vec![webhook.clone()], &path).unwrap();
+        assert_eq!((webhooks.pending(), webhooks.dead_letters()), (0, 2));
+        let outbox = Outbox::load(&path).unwrap();
+        assert_eq!(outbox.dead_letters.iter().map(|d| (d.id, d.attempts)).collect::<Vec<_>>(), [(1, MAX_ATTEMPTS), (2, MAX_ATTEMPTS)]);
+
+        // deliveries of a webhook removed from the config are kept as dead letters too
+        let path = dir.join("removed.jsonl");
+        chargeback(&Arc::new(Webhooks::open(vec![webhook], &path).unwrap()));
+        let webhooks = Webhooks::open(vec![], &path).unwrap();
+        assert_eq!(webhooks.deliver_due(now_millis()).unwrap(), 0);
+        assert_eq!((webhooks.pending(), webhooks.dead_letters()), (0, 2));
+    }
+
+    #[test]
+    /// the end of processing waits for retries due soon
+    fn finish_retries() {
+        let dir = TempDir::new("webhook-finish");
+
+        let (url, received) = stub_receiver(2);
+        let webhook = Webhook {
+            url,
+            secret: "s3cret".to_string(),
+            events: vec![EventKind::ChargebackApplied, EventKind::AccountLocked],
+        };
+
+        let webhooks = Arc::new(Webhooks::open(vec![webhook], dir.join("outbox.jsonl")).unwrap());
+        chargeback(&webhooks);
+
+        assert_eq!(webhooks.finish().unwrap(), 0);
+        assert_eq!(posted(&received), ["ChargebackApplied", "ChargebackApplied", "ChargebackApplied", "AccountLocked"]);
+    }
+}